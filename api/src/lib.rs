@@ -1,3 +1,7 @@
+use std::fmt;
+
+use base64urlsafedata::Base64UrlSafeData;
+use keygen::{Algorithm, Version};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use webauthn_rs_proto::{
@@ -5,6 +9,102 @@ use webauthn_rs_proto::{
     RequestChallengeResponse,
 };
 
+/// Stable error codes of the API, each with its HTTP status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request body is malformed.
+    BadRequest,
+    /// A finish request without a matching ceremony in the session.
+    NoCeremony,
+    /// The authenticator's response is unusable for the ceremony.
+    CeremonyFailed,
+    /// The authenticator's assertion did not verify for a login.
+    AuthenticationFailed,
+    /// The ceremony was started too long ago, it has to be started again.
+    CeremonyExpired,
+    /// A proof of possession for a derived key did not verify.
+    InvalidProof,
+    /// The endpoint requires a logged in session.
+    Unauthenticated,
+    /// The endpoint would replace a logged in session.
+    AlreadyAuthenticated,
+    UserNotFound,
+    CredentialNotFound,
+    /// The user has not completed a registration that binds its keys.
+    KeysNotFound,
+    UserExists,
+    CredentialExists,
+    /// Revoking the credential would lock the user out.
+    LastCredential,
+    /// The derived key differs from the one bound to the credential.
+    KeyMismatch,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::BadRequest
+            | Self::NoCeremony
+            | Self::CeremonyFailed
+            | Self::CeremonyExpired
+            | Self::InvalidProof => 400,
+            Self::Unauthenticated | Self::AuthenticationFailed => 401,
+            Self::UserNotFound | Self::CredentialNotFound | Self::KeysNotFound => 404,
+            Self::UserExists
+            | Self::CredentialExists
+            | Self::LastCredential
+            | Self::KeyMismatch
+            | Self::AlreadyAuthenticated => 409,
+            Self::Internal => 500,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BadRequest => "bad_request",
+            Self::NoCeremony => "no_ceremony",
+            Self::CeremonyFailed => "ceremony_failed",
+            Self::AuthenticationFailed => "authentication_failed",
+            Self::CeremonyExpired => "ceremony_expired",
+            Self::InvalidProof => "invalid_proof",
+            Self::Unauthenticated => "unauthenticated",
+            Self::AlreadyAuthenticated => "already_authenticated",
+            Self::UserNotFound => "user_not_found",
+            Self::CredentialNotFound => "credential_not_found",
+            Self::KeysNotFound => "keys_not_found",
+            Self::UserExists => "user_exists",
+            Self::CredentialExists => "credential_exists",
+            Self::LastCredential => "last_credential",
+            Self::KeyMismatch => "key_mismatch",
+            Self::Internal => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Body of error responses; `code` is stable and meant to be matched on,
+/// `error` is a human readable message.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub code: ErrorCode,
+}
+
+impl fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.error, self.code)
+    }
+}
+
+impl std::error::Error for ErrorResponse {}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StartRegistrationRequest {
     pub did: String,
@@ -19,6 +119,17 @@ pub struct StartRegistrationResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FinishRegistrationRequest {
     pub credential: RegisterPublicKeyCredential,
+    pub derivation: Version,
+    pub signing_key: KeyProof,
+    pub rotation_key: KeyProof,
+}
+
+/// A derived public key with a signature over the ceremony challenge, see
+/// `keygen::proof`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyProof {
+    pub did_key: String,
+    pub signature: Base64UrlSafeData,
 }
 
 // TODO: Drop
@@ -35,14 +146,151 @@ pub struct StartAuthenticationRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StartAuthenticationResponse {
     pub challenge: RequestChallengeResponse,
+    pub derivations: Vec<CredentialDerivation>,
+}
+
+/// The key derivation scheme and algorithm the client used when registering
+/// a credential.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CredentialDerivation {
+    pub credential_id: Base64UrlSafeData,
+    pub derivation: Version,
+    /// Unknown for credentials from before keys were bound, until their
+    /// first login.
+    pub algorithm: Option<Algorithm>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FinishAuthenticationRequest {
     pub credential: PublicKeyCredential,
+    pub signing_key: KeyProof,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FinishAuthenticationResponse {
     pub user_id: Uuid,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartDiscoverableAuthenticationRequest {
+    /// Whether the login is for the autofill of the DID input (conditional
+    /// mediation), which is refused for a logged in session.
+    pub conditional: bool,
+}
+
+/// A challenge without allowed credentials, answered by any discoverable
+/// credential of the relying party.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartDiscoverableAuthenticationResponse {
+    pub challenge: RequestChallengeResponse,
+    /// Seconds until the challenge expires, so that autofill can be re-armed
+    /// with a fresh one before.
+    pub expires_in_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FinishDiscoverableAuthenticationRequest {
+    pub credential: PublicKeyCredential,
+}
+
+/// The user resolved from the credential's user handle, with what the client
+/// needs to derive its keys. The session is only logged in once the derived
+/// key is verified.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FinishDiscoverableAuthenticationResponse {
+    pub user_id: Uuid,
+    pub did: String,
+    pub derivation: Version,
+    pub algorithm: Option<Algorithm>,
+}
+
+/// The derived signing key with a signature over the challenge of the
+/// finished discoverable authentication.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyDiscoverableAuthenticationRequest {
+    pub signing_key: KeyProof,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyDiscoverableAuthenticationResponse {
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LookupKeysResponse {
+    pub did: String,
+    /// The signing keys bound to the user's credentials, oldest first.
+    pub signing_keys: Vec<String>,
+    /// Only published while the credential that registered the DID remains.
+    pub rotation_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MeResponse {
+    pub user_id: Uuid,
+    pub did: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogoutResponse {
+    pub user_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAccountResponse {
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartAddCredentialResponse {
+    pub challenge: CreationChallengeResponse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FinishAddCredentialRequest {
+    pub credential: RegisterPublicKeyCredential,
+    pub derivation: Version,
+    pub signing_key: KeyProof,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FinishAddCredentialResponse {
+    pub credential_id: Base64UrlSafeData,
+}
+
+/// A registered passkey; timestamps are seconds since the unix epoch.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CredentialInfo {
+    pub credential_id: Base64UrlSafeData,
+    pub nickname: Option<String>,
+    pub created_at: u64,
+    pub last_used_at: Option<u64>,
+    pub backup_state: bool,
+    pub derivation: Version,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListCredentialsResponse {
+    pub credentials: Vec<CredentialInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameCredentialRequest {
+    pub credential_id: Base64UrlSafeData,
+    pub nickname: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameCredentialResponse {
+    pub credential: CredentialInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeCredentialRequest {
+    pub credential_id: Base64UrlSafeData,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeCredentialResponse {
+    pub credentials: Vec<CredentialInfo>,
+}
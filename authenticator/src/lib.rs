@@ -0,0 +1,329 @@
+//! A software WebAuthn authenticator with PRF support, to run the ceremonies
+//! without a browser and security key, e.g. in tests and command line tools.
+//!
+//! Credentials are ES256 keys with `none` attestation. The PRF extension is
+//! evaluated like CTAP2 `hmac-secret`, with a random secret per credential.
+//! An authenticator serializes to JSON, including its private keys, so it can
+//! be kept in a file.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, bail};
+use base64urlsafedata::Base64UrlSafeData;
+use data_encoding::BASE64URL_NOPAD;
+use keygen::Secret;
+use ring::digest::{SHA256, digest};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, KeyPair};
+use serde::{Deserialize, Serialize};
+use serde_cbor_2::Value;
+use url::Url;
+use webauthn_rs_proto::{
+    AuthenticationExtensionsClientOutputs, AuthenticatorAssertionResponseRaw,
+    AuthenticatorAttestationResponseRaw, CollectedClientData, CreationChallengeResponse,
+    PublicKeyCredential, RegisterPublicKeyCredential, RegistrationExtensionsClientOutputs,
+    RequestChallengeResponse,
+};
+
+/// COSE algorithm identifier of ES256.
+const COSE_ES256: i128 = -7;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// Prefix of the PRF inputs before hashing them into `hmac-secret` salts.
+const PRF_CONTEXT: &[u8] = b"WebAuthn PRF\0";
+
+const PUBLIC_KEY: &str = "public-key";
+
+/// An authenticator holding discoverable credentials.
+#[derive(Serialize, Deserialize)]
+pub struct SoftPasskey {
+    origin: Url,
+    credentials: Vec<SoftCredential>,
+    #[serde(skip, default = "SystemRandom::new")]
+    rng: SystemRandom,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SoftCredential {
+    #[serde(with = "base64url")]
+    id: Vec<u8>,
+    rp_id: String,
+    #[serde(with = "base64url")]
+    user_handle: Vec<u8>,
+    #[serde(with = "base64url")]
+    pkcs8: Secret,
+    #[serde(with = "base64url")]
+    prf_secret: Secret,
+    counter: u32,
+}
+
+/// A created credential, with the PRF output for the requested input.
+#[derive(Debug)]
+pub struct Registration {
+    pub credential: RegisterPublicKeyCredential,
+    pub prf: Secret,
+}
+
+/// An assertion of an existing credential, with the PRF output for the
+/// requested input.
+#[derive(Debug)]
+pub struct Assertion {
+    pub credential: PublicKeyCredential,
+    pub prf: Secret,
+}
+
+impl SoftPasskey {
+    /// An authenticator without credentials, used by a client at `origin`.
+    pub fn new(origin: Url) -> Self {
+        Self {
+            origin,
+            credentials: Vec::new(),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    /// Creates a credential like `navigator.credentials.create()` with the
+    /// PRF extension evaluated for `prf_input`.
+    pub fn create(
+        &mut self,
+        options: &CreationChallengeResponse,
+        prf_input: &[u8],
+    ) -> Result<Registration> {
+        let options = &options.public_key;
+
+        if !options
+            .pub_key_cred_params
+            .iter()
+            .any(|param| i128::from(param.alg) == COSE_ES256)
+        {
+            bail!("ES256 is not an accepted credential algorithm");
+        }
+
+        let excluded = options
+            .exclude_credentials
+            .iter()
+            .flatten()
+            .any(|excluded| {
+                self.credentials
+                    .iter()
+                    .any(|credential| credential.id == excluded.id.as_slice())
+            });
+
+        if excluded {
+            bail!("credential already registered");
+        }
+
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &self.rng)
+            .ok()
+            .context("failed to generate credential key")?;
+
+        let credential = SoftCredential {
+            id: self.random(32)?.to_vec(),
+            rp_id: options.rp.id.clone(),
+            user_handle: options.user.id.to_vec(),
+            pkcs8: Secret::new(pkcs8.as_ref().to_vec()),
+            prf_secret: self.random(32)?,
+            counter: 0,
+        };
+
+        let keypair = credential.keypair(&self.rng)?;
+
+        let mut auth_data = credential.auth_data(FLAG_ATTESTED_CREDENTIAL);
+        auth_data.extend_from_slice(&[0; 16]);
+        auth_data.extend_from_slice(&(credential.id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&credential.id);
+        auth_data.extend_from_slice(&cose_key(keypair.public_key().as_ref())?);
+
+        let attestation_object = serde_cbor_2::to_vec(&Value::Map(BTreeMap::from([
+            (text("fmt"), text("none")),
+            (text("attStmt"), Value::Map(BTreeMap::new())),
+            (text("authData"), Value::Bytes(auth_data)),
+        ])))?;
+
+        let client_data_json =
+            Self::client_data(&self.origin, "webauthn.create", &options.challenge)?;
+        let prf = credential.prf(prf_input);
+
+        let registration = Registration {
+            credential: RegisterPublicKeyCredential {
+                id: BASE64URL_NOPAD.encode(&credential.id),
+                raw_id: credential.id.clone().into(),
+                response: AuthenticatorAttestationResponseRaw {
+                    attestation_object: attestation_object.into(),
+                    client_data_json: client_data_json.into(),
+                    transports: None,
+                },
+                type_: PUBLIC_KEY.to_owned(),
+                extensions: RegistrationExtensionsClientOutputs::default(),
+            },
+            prf,
+        };
+
+        self.credentials.push(credential);
+
+        Ok(registration)
+    }
+
+    /// Asserts a credential like `navigator.credentials.get()` with the PRF
+    /// extension evaluated for `prf_input`. Without allowed credentials in the
+    /// options, the most recently created one for the relying party is used.
+    pub fn get(
+        &mut self,
+        options: &RequestChallengeResponse,
+        prf_input: &[u8],
+    ) -> Result<Assertion> {
+        let options = &options.public_key;
+
+        let credential = self
+            .credentials
+            .iter_mut()
+            .rev()
+            .filter(|credential| credential.rp_id == options.rp_id)
+            .find(|credential| {
+                options.allow_credentials.is_empty()
+                    || options
+                        .allow_credentials
+                        .iter()
+                        .any(|allowed| credential.id == allowed.id.as_slice())
+            })
+            .context("no matching credential")?;
+
+        credential.counter += 1;
+
+        let auth_data = credential.auth_data(0);
+
+        let client_data_json = Self::client_data(&self.origin, "webauthn.get", &options.challenge)?;
+
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(digest(&SHA256, &client_data_json).as_ref());
+
+        let signature = credential
+            .keypair(&self.rng)?
+            .sign(&self.rng, &signed)
+            .ok()
+            .context("failed to sign assertion")?;
+
+        Ok(Assertion {
+            credential: PublicKeyCredential {
+                id: BASE64URL_NOPAD.encode(&credential.id),
+                raw_id: credential.id.clone().into(),
+                response: AuthenticatorAssertionResponseRaw {
+                    authenticator_data: auth_data.into(),
+                    client_data_json: client_data_json.into(),
+                    signature: signature.as_ref().to_vec().into(),
+                    user_handle: Some(credential.user_handle.clone().into()),
+                },
+                extensions: AuthenticationExtensionsClientOutputs::default(),
+                type_: PUBLIC_KEY.to_owned(),
+            },
+            prf: credential.prf(prf_input),
+        })
+    }
+
+    fn client_data(origin: &Url, type_: &str, challenge: &Base64UrlSafeData) -> Result<Vec<u8>> {
+        let client_data = CollectedClientData {
+            type_: type_.to_owned(),
+            challenge: challenge.clone(),
+            origin: origin.clone(),
+            cross_origin: Some(false),
+            token_binding: None,
+            unknown_keys: BTreeMap::new(),
+        };
+
+        Ok(serde_json::to_vec(&client_data)?)
+    }
+
+    fn random(&self, len: usize) -> Result<Secret> {
+        let mut bytes = Secret::new(vec![0; len]);
+
+        self.rng
+            .fill(&mut bytes)
+            .ok()
+            .context("failed to generate random bytes")?;
+
+        Ok(bytes)
+    }
+}
+
+impl SoftCredential {
+    fn keypair(&self, rng: &SystemRandom) -> Result<EcdsaKeyPair> {
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &self.pkcs8, rng)
+            .ok()
+            .context("malformed credential key")
+    }
+
+    /// The authenticator data up to the attested credential data; user
+    /// presence and verification are always asserted.
+    fn auth_data(&self, flags: u8) -> Vec<u8> {
+        let mut auth_data = digest(&SHA256, self.rp_id.as_bytes()).as_ref().to_vec();
+        auth_data.push(FLAG_USER_PRESENT | FLAG_USER_VERIFIED | flags);
+        auth_data.extend_from_slice(&self.counter.to_be_bytes());
+        auth_data
+    }
+
+    fn prf(&self, input: &[u8]) -> Secret {
+        let mut salt = PRF_CONTEXT.to_vec();
+        salt.extend_from_slice(input);
+
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &self.prf_secret);
+        let tag = hmac::sign(&key, digest(&SHA256, &salt).as_ref());
+
+        Secret::new(tag.as_ref().to_vec())
+    }
+}
+
+/// Encodes an uncompressed P-256 public key as COSE_Key.
+fn cose_key(public_key: &[u8]) -> Result<Vec<u8>> {
+    let Some((&0x04, xy)) = public_key.split_first() else {
+        bail!("public key is not uncompressed");
+    };
+
+    let (x, y) = xy.split_at(32);
+
+    let key = Value::Map(BTreeMap::from([
+        (Value::Integer(1), Value::Integer(2)),
+        (Value::Integer(3), Value::Integer(COSE_ES256)),
+        (Value::Integer(-1), Value::Integer(1)),
+        (Value::Integer(-2), Value::Bytes(x.to_vec())),
+        (Value::Integer(-3), Value::Bytes(y.to_vec())),
+    ]));
+
+    Ok(serde_cbor_2::to_vec(&key)?)
+}
+
+fn text(text: &str) -> Value {
+    Value::Text(text.to_owned())
+}
+
+/// Bytes as base64url strings, to keep serialized authenticators readable.
+mod base64url {
+    use std::ops::Deref;
+
+    use data_encoding::BASE64URL_NOPAD;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub(crate) fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Deref<Target = [u8]>,
+        S: Serializer,
+    {
+        serializer.serialize_str(&BASE64URL_NOPAD.encode(bytes))
+    }
+
+    pub(crate) fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: From<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        let encoded = String::deserialize(deserializer)?;
+
+        BASE64URL_NOPAD
+            .decode(encoded.as_bytes())
+            .map(T::from)
+            .map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::{Context, Result};
+use api::*;
+use reqwest::{Certificate, Method, RequestBuilder, Response};
+use serde::{Serialize, de::DeserializeOwned};
+use url::Url;
+
+/// Talks to the server like the WASM client, keeping the session cookie
+/// between requests.
+pub(crate) struct Client {
+    http: reqwest::Client,
+    server: Url,
+}
+
+impl Client {
+    pub(crate) fn new(server: Url, ca_cert: Option<&[u8]>, insecure: bool) -> Result<Self> {
+        let mut http = reqwest::Client::builder()
+            .cookie_store(true)
+            .danger_accept_invalid_certs(insecure);
+
+        if let Some(pem) = ca_cert {
+            let cert = Certificate::from_pem(pem).context("malformed ca certificate")?;
+            http = http.add_root_certificate(cert);
+        }
+
+        let http = http.build().context("failed to build http client")?;
+
+        Ok(Self { http, server })
+    }
+
+    pub(crate) async fn auth_start(
+        &self,
+        req: StartAuthenticationRequest,
+    ) -> Result<StartAuthenticationResponse> {
+        self.post("auth/start", &req).await
+    }
+
+    pub(crate) async fn auth_finish(
+        &self,
+        req: FinishAuthenticationRequest,
+    ) -> Result<FinishAuthenticationResponse> {
+        self.post("auth/finish", &req).await
+    }
+
+    pub(crate) async fn register_start(
+        &self,
+        req: StartRegistrationRequest,
+    ) -> Result<StartRegistrationResponse> {
+        self.post("register/start", &req).await
+    }
+
+    pub(crate) async fn register_finish(
+        &self,
+        req: FinishRegistrationRequest,
+    ) -> Result<FinishRegistrationResponse> {
+        self.post("register/finish", &req).await
+    }
+
+    pub(crate) async fn lookup_keys(&self, did: &str) -> Result<LookupKeysResponse> {
+        self.get(&format!("keys/{did}")).await
+    }
+
+    pub(crate) async fn account_delete(&self) -> Result<DeleteAccountResponse> {
+        self.post("account/delete", &()).await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.send(self.request(Method::GET, path)?).await
+    }
+
+    async fn post<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T> {
+        self.send(self.request(Method::POST, path)?.json(body))
+            .await
+    }
+
+    fn request(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        let url = self.server.join(path).context("invalid request path")?;
+        Ok(self.http.request(method, url))
+    }
+
+    async fn send<T: DeserializeOwned>(&self, req: RequestBuilder) -> Result<T> {
+        let res = req.send().await.context("failed to send request")?;
+        let res = Self::check(res).await?;
+
+        res.json().await.context("failed to parse response")
+    }
+
+    /// Turns error responses into [`ErrorResponse`] errors.
+    async fn check(res: Response) -> Result<Response> {
+        if res.status().is_success() {
+            return Ok(res);
+        }
+
+        let status = res.status();
+        let err: ErrorResponse = res
+            .json()
+            .await
+            .context(format!("request failed with {status}"))?;
+
+        Err(err.into())
+    }
+}
@@ -0,0 +1,265 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use api::{
+    FinishAuthenticationRequest, FinishRegistrationRequest, KeyProof, StartAuthenticationRequest,
+    StartRegistrationRequest,
+};
+use authenticator::SoftPasskey;
+use clap::{Parser, Subcommand};
+use data_encoding::BASE64URL_NOPAD;
+use keygen::{Algorithm, Keygen, Keypair, Keys, Version, didkey, jws, proof};
+use serde_json::{Value, json};
+use url::Url;
+
+use crate::client::Client;
+
+mod client;
+
+/// Command line client for the demo server, with a software authenticator
+/// kept in a file instead of a browser and passkey.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Server URL
+    #[arg(long, env = "CLI_SERVER", default_value = "https://localhost:9999/")]
+    server: Url,
+
+    /// Software authenticator file, created on first use
+    #[arg(long, env = "CLI_AUTHENTICATOR", default_value = "authenticator.json")]
+    authenticator: PathBuf,
+
+    /// CA certificate (PEM) to trust for the server, e.g. certs/cert.pem
+    #[arg(long, env = "CLI_CA_CERT")]
+    ca_cert: Option<PathBuf>,
+
+    /// Accept any server certificate
+    #[arg(long)]
+    insecure: bool,
+
+    /// Algorithm of the derived keys on registration: p256, secp256k1 or
+    /// ed25519; logins use the one recorded for the credential
+    #[arg(long, default_value = "p256")]
+    algorithm: Algorithm,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Registers the DID with a new credential of the authenticator
+    Register { did: String },
+    /// Logs in and prints the derived keys
+    Login { did: String },
+    /// Logs in and signs the message with the derived signing key
+    Sign { did: String, message: String },
+    /// Logs in and signs the JSON claims as a compact JWT
+    Jwt { did: String, claims: String },
+    /// Prints the keys bound to the DID on the server
+    Lookup { did: String },
+    /// Logs in and deletes the account with its credentials on the server
+    Delete { did: String },
+}
+
+struct Cli {
+    client: Client,
+    passkey: SoftPasskey,
+    path: PathBuf,
+    algorithm: Algorithm,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    rustls::crypto::aws_lc_rs::default_provider()
+        .install_default()
+        .ok()
+        .context("failed to install crypto provider")?;
+
+    let ca_cert = args
+        .ca_cert
+        .as_deref()
+        .map(|path| {
+            fs::read(path).context(format!("failed to read ca certificate {}", path.display()))
+        })
+        .transpose()?;
+
+    let passkey = load(&args.authenticator, &args.server)?;
+
+    let mut cli = Cli {
+        client: Client::new(args.server, ca_cert.as_deref(), args.insecure)?,
+        passkey,
+        path: args.authenticator,
+        algorithm: args.algorithm,
+    };
+
+    let output = match args.command {
+        Command::Register { did } => cli.register(&did).await?,
+        Command::Login { did } => {
+            let keys = cli.login(&did).await?;
+
+            json!({
+                "did": did,
+                "signingKey": did_key(&keys.signing)?,
+                "rotationKey": did_key(&keys.rotation)?,
+            })
+        }
+        Command::Sign { did, message } => {
+            let keys = cli.login(&did).await?;
+            let algorithm = keys.signing.algorithm();
+            let signature = keys.signing.sign(message.as_bytes())?;
+            let der = algorithm.der_signature(&signature)?;
+
+            json!({
+                "algorithm": algorithm.as_str(),
+                "didKey": did_key(&keys.signing)?,
+                "signature": BASE64URL_NOPAD.encode(&signature),
+                "der": der.map(|der| BASE64URL_NOPAD.encode(&der)),
+            })
+        }
+        Command::Jwt { did, claims } => {
+            let claims: Value = serde_json::from_str(&claims).context("malformed jwt claims")?;
+            let keys = cli.login(&did).await?;
+
+            Value::String(jws::sign(&keys.signing, &claims)?)
+        }
+        Command::Lookup { did } => serde_json::to_value(cli.client.lookup_keys(&did).await?)?,
+        Command::Delete { did } => {
+            cli.login(&did).await?;
+
+            serde_json::to_value(cli.client.account_delete().await?)?
+        }
+    };
+
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    Ok(())
+}
+
+impl Cli {
+    /// Runs the registration ceremony, like the WASM client.
+    async fn register(&mut self, did: &str) -> Result<Value> {
+        let res = self
+            .client
+            .register_start(StartRegistrationRequest {
+                did: did.to_owned(),
+            })
+            .await?;
+
+        let registration = self.passkey.create(&res.challenge, b"")?;
+        save(&self.path, &self.passkey)?;
+
+        let keys = Keygen::new(Version::LATEST).generate_keys(
+            &registration.prf,
+            did.as_bytes(),
+            self.algorithm,
+        )?;
+
+        let challenge = &res.challenge.public_key.challenge;
+        let signing_key = key_proof(&keys.signing, did, challenge)?;
+        let rotation_key = key_proof(&keys.rotation, did, challenge)?;
+
+        let output = json!({
+            "did": did,
+            "userId": res.user_id,
+            "signingKey": signing_key.did_key,
+            "rotationKey": rotation_key.did_key,
+        });
+
+        self.client
+            .register_finish(FinishRegistrationRequest {
+                credential: registration.credential,
+                derivation: Version::LATEST,
+                signing_key,
+                rotation_key,
+            })
+            .await?;
+
+        Ok(output)
+    }
+
+    /// Runs the authentication ceremony and derives the keys from its PRF,
+    /// like the WASM client.
+    async fn login(&mut self, did: &str) -> Result<Keys> {
+        let res = self
+            .client
+            .auth_start(StartAuthenticationRequest {
+                did: did.to_owned(),
+            })
+            .await?;
+
+        let assertion = self.passkey.get(&res.challenge, b"")?;
+        save(&self.path, &self.passkey)?;
+
+        let derivation = res
+            .derivations
+            .iter()
+            .find(|derivation| derivation.credential_id == assertion.credential.raw_id)
+            .context("no derivation version for credential")?;
+
+        // --algorithm only applies to credentials from before it was recorded
+        let algorithm = derivation.algorithm.unwrap_or(self.algorithm);
+
+        let keys = Keygen::new(derivation.derivation).generate_keys(
+            &assertion.prf,
+            did.as_bytes(),
+            algorithm,
+        )?;
+
+        let challenge = &res.challenge.public_key.challenge;
+
+        self.client
+            .auth_finish(FinishAuthenticationRequest {
+                credential: assertion.credential,
+                signing_key: key_proof(&keys.signing, did, challenge)?,
+            })
+            .await?;
+
+        Ok(keys)
+    }
+}
+
+/// Loads the authenticator file, or creates a new authenticator for the
+/// server's origin.
+fn load(path: &Path, server: &Url) -> Result<SoftPasskey> {
+    if !path.exists() {
+        return Ok(SoftPasskey::new(server.clone()));
+    }
+
+    let contents =
+        fs::read(path).context(format!("failed to read authenticator {}", path.display()))?;
+
+    serde_json::from_slice(&contents).context(format!("malformed authenticator {}", path.display()))
+}
+
+/// Saves the authenticator, readable by the owner only as it holds the
+/// credentials' private keys.
+fn save(path: &Path, passkey: &SoftPasskey) -> Result<()> {
+    let contents = serde_json::to_vec_pretty(passkey)?;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(&contents))
+        .context(format!("failed to write authenticator {}", path.display()))
+}
+
+fn did_key(keypair: &Keypair) -> Result<String> {
+    didkey::encode(keypair.algorithm(), &keypair.public_key())
+}
+
+fn key_proof(keypair: &Keypair, did: &str, challenge: &[u8]) -> Result<KeyProof> {
+    Ok(KeyProof {
+        did_key: did_key(keypair)?,
+        signature: proof::sign(keypair, did, challenge)?.into(),
+    })
+}
@@ -1,30 +1,45 @@
-use std::cell::OnceCell;
+use std::cell::{OnceCell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
 
 use api::{
-    FinishAuthenticationRequest, FinishRegistrationRequest, StartAuthenticationRequest,
-    StartRegistrationRequest,
+    ErrorCode, FinishAddCredentialRequest, FinishAuthenticationRequest,
+    FinishDiscoverableAuthenticationRequest, FinishRegistrationRequest, KeyProof,
+    StartAuthenticationRequest, StartDiscoverableAuthenticationRequest, StartRegistrationRequest,
+    VerifyDiscoverableAuthenticationRequest,
 };
 use gloo_events::EventListener;
-use ring::signature::KeyPair;
+use gloo_timers::callback::Timeout;
+use js_sys::{Object, Uint8Array};
+use keygen::plc::{Operation, PlcDirectory};
+use keygen::{Algorithm, Keygen, Keypair, Secret, Version, didkey, jws, proof};
 use tracing::{error, info, instrument};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
-    CredentialCreationOptions, CredentialRequestOptions, Document, HtmlInputElement,
-    PublicKeyCredential, Window,
+    AbortController, AbortSignal, CredentialCreationOptions, CredentialRequestOptions, Document,
+    HtmlInputElement, HtmlSelectElement, PublicKeyCredential, Window,
 };
+use webauthn_rs_proto::{Mediation, RequestChallengeResponse};
 
 use crate::client::Client;
 use crate::error::{Context, Error, Result};
-use crate::keygen::Keygen;
-use crate::util::{CredentialOptionsExt, DocumentExt, PublicKeyCredentialExt, ValueExt};
+use crate::plc;
+use crate::signer::{LoginKeys, Signer};
+use crate::util::{CredentialOptionsExt, DocumentExt, ObjectExt, PublicKeyCredentialExt, ValueExt};
+
+/// Milliseconds per second of a challenge's lifetime after which autofill is
+/// re-armed, leaving a quarter of it to finish the login after a late pick.
+const REARM_PERMILLE: u64 = 750;
 
 #[wasm_bindgen]
 pub(crate) struct Application {
     client: Rc<Client>,
     document: Document,
+    /// Signing key derived by the last login, reused for signing.
+    signer: RefCell<Option<Rc<Signer>>>,
+    /// Aborts the pending autofill login.
+    conditional: RefCell<Option<AbortController>>,
     listeners: OnceCell<Vec<EventListener>>,
     window: Window,
 }
@@ -36,12 +51,18 @@ impl Application {
 
         let register = document.id("b2c3d4e5-f6g7-8901-bcde-f12345678901")?;
         let login = document.id("5c492801-6acb-4657-a000-4ce99d5540a3")?;
+        let add_device = document.id("9f1d6a3e-2c47-4b8e-a5d0-7e3b91c24f68")?;
+        let logout = document.id("3a7e5c19-0b64-4d2f-8e91-c6f2d84b7a05")?;
+        let create_plc = document.id("c1d7a4e9-5b32-4f68-a0e3-9e2b6d8f4c15")?;
 
-        let endpoint = "localhost:9999";
+        // The page is served by the API server, wherever it is configured to run
+        let endpoint = window.location().host().map_err(Error::from_js_value)?;
 
         let webauthn = Rc::new(Self {
-            client: Rc::new(Client::new(endpoint)),
+            client: Rc::new(Client::new(&endpoint)),
             document,
+            signer: RefCell::new(None),
+            conditional: RefCell::new(None),
             listeners: OnceCell::new(),
             window,
         });
@@ -49,6 +70,8 @@ impl Application {
         let register = EventListener::new(&register, "click", {
             let webauthn = webauthn.clone();
             move |_event| {
+                webauthn.abort_conditional();
+
                 wasm_bindgen_futures::spawn_local({
                     let webauthn = webauthn.clone();
 
@@ -56,8 +79,9 @@ impl Application {
                         if let Err(err) = webauthn.register_user().await {
                             error!(err = ?err, "failure while registering user");
                             webauthn
-                                .update_status("Registration failed", "error")
+                                .report_failure("Registration failed", &err)
                                 .unwrap_throw();
+                            webauthn.start_conditional_login();
                         }
                     }
                 });
@@ -67,14 +91,74 @@ impl Application {
         let login = EventListener::new(&login, "click", {
             let webauthn = webauthn.clone();
             move |_event| {
+                webauthn.abort_conditional();
+
                 wasm_bindgen_futures::spawn_local({
                     let webauthn = webauthn.clone();
                     async move {
                         if let Err(err) = webauthn.login_user().await {
                             error!(err = ?err, "failure while logging in user");
+                            webauthn.report_failure("Login failed", &err).unwrap_throw();
+                            webauthn.start_conditional_login();
+                        }
+                    }
+                });
+            }
+        });
+
+        let add_device = EventListener::new(&add_device, "click", {
+            let webauthn = webauthn.clone();
+            move |_event| {
+                webauthn.abort_conditional();
+
+                wasm_bindgen_futures::spawn_local({
+                    let webauthn = webauthn.clone();
+                    async move {
+                        if let Err(err) = webauthn.add_credential().await {
+                            error!(err = ?err, "failure while adding credential");
                             webauthn
-                                .update_status("Login failed", "error")
+                                .report_failure("Adding device failed", &err)
                                 .unwrap_throw();
+                            webauthn.start_conditional_login();
+                        }
+                    }
+                });
+            }
+        });
+
+        let logout = EventListener::new(&logout, "click", {
+            let webauthn = webauthn.clone();
+            move |_event| {
+                webauthn.abort_conditional();
+
+                wasm_bindgen_futures::spawn_local({
+                    let webauthn = webauthn.clone();
+                    async move {
+                        if let Err(err) = webauthn.logout_user().await {
+                            error!(err = ?err, "failure while logging out user");
+                            webauthn
+                                .report_failure("Logout failed", &err)
+                                .unwrap_throw();
+                        }
+                    }
+                });
+            }
+        });
+
+        let create_plc = EventListener::new(&create_plc, "click", {
+            let webauthn = webauthn.clone();
+            move |_event| {
+                webauthn.abort_conditional();
+
+                wasm_bindgen_futures::spawn_local({
+                    let webauthn = webauthn.clone();
+                    async move {
+                        if let Err(err) = webauthn.create_plc().await {
+                            error!(err = ?err, "failure while creating did:plc");
+                            webauthn
+                                .report_failure("Creating did:plc failed", &err)
+                                .unwrap_throw();
+                            webauthn.start_conditional_login();
                         }
                     }
                 });
@@ -83,7 +167,7 @@ impl Application {
 
         webauthn
             .listeners
-            .set(vec![register, login])
+            .set(vec![register, login, add_device, logout, create_plc])
             .map_err(|_| Error::new("failed to setup listeners"))?;
 
         Ok(webauthn)
@@ -109,19 +193,213 @@ impl Application {
         Ok(capabilities)
     }
 
+    /// Shows a failure, with the server's reason if the API rejected the
+    /// request.
+    fn report_failure(self: &Rc<Self>, msg: &str, err: &Error) -> Result<()> {
+        match err.api() {
+            Some(res) => self.update_status(&format!("{msg}: {}", res.error), "error"),
+            None => self.update_status(msg, "error"),
+        }
+    }
+
     pub async fn has_prf_support(&self) -> Result<bool> {
         let caps = self.capabilities().await?;
         Ok(caps.contains_key("extension:prf"))
     }
 
+    async fn has_conditional_support(&self) -> Result<bool> {
+        let caps = self.capabilities().await?;
+        Ok(caps.get("conditionalGet").copied().unwrap_or_default())
+    }
+
+    /// Offers the discoverable credentials in the autofill of the DID input,
+    /// logging in when one is picked, until a button aborts it.
+    pub(crate) fn start_conditional_login(self: &Rc<Self>) {
+        wasm_bindgen_futures::spawn_local({
+            let webauthn = self.clone();
+
+            async move {
+                if let Err(err) = webauthn.conditional_login().await {
+                    error!(err = ?err, "failure while logging in user from autofill");
+                    webauthn.report_failure("Login failed", &err).unwrap_throw();
+
+                    // Autofill can stay open for longer than a ceremony, or
+                    // even the session holding it, lasts
+                    if err.api().is_some_and(|res| {
+                        matches!(res.code, ErrorCode::CeremonyExpired | ErrorCode::NoCeremony)
+                    }) {
+                        webauthn.start_conditional_login();
+                    }
+                }
+            }
+        });
+    }
+
+    #[instrument(skip(self))]
+    async fn conditional_login(self: &Rc<Self>) -> Result<()> {
+        if !self.has_conditional_support().await? {
+            info!("Conditional mediation is not supported");
+            return Ok(());
+        }
+
+        // The session outlives a reload, autofill is for logged out users only
+        if let Ok(me) = self.client.auth_me().await {
+            info!(did = %me.did, "Already logged in, not offering autofill");
+            return Ok(());
+        }
+
+        let controller = AbortController::new().map_err(Error::from_js_value)?;
+        let signal = controller.signal();
+
+        if let Some(pending) = self.conditional.replace(Some(controller)) {
+            pending.abort();
+        }
+
+        let keys = match self.authenticate_discoverable(Some(&signal)).await {
+            Ok(keys) => keys,
+            // The user started another ceremony instead
+            Err(_) if signal.aborted() => return Ok(()),
+            Err(err) => {
+                self.conditional.take();
+                return Err(err);
+            }
+        };
+
+        self.conditional.take();
+
+        self.report_login(&keys)
+    }
+
+    /// Aborts the pending autofill login before a button starts another
+    /// ceremony, as browsers only allow one pending credential request. A
+    /// failed ceremony re-arms it, which is a no-op once logged in.
+    fn abort_conditional(&self) {
+        if let Some(controller) = self.conditional.take() {
+            controller.abort();
+        }
+    }
+
     #[instrument(skip(self))]
     async fn login_user(self: &Rc<Self>) -> Result<()> {
+        let keys = self.authenticate().await?;
+
+        self.report_login(&keys)
+    }
+
+    fn report_login(self: &Rc<Self>, keys: &LoginKeys) -> Result<()> {
+        let did_key = keys.signer.did_key()?;
+        let rotation_did_key =
+            didkey::encode(keys.rotation.algorithm(), &keys.rotation.public_key())?;
+
+        info!(
+            algorithm = ?keys.signer.algorithm(),
+            did_key = %did_key,
+            rotation_did_key = %rotation_did_key,
+            "Generated keypairs for login"
+        );
+
+        self.update_status(
+            &format!(
+                "Login successful - public key: {} - rotation key: {}",
+                did_key, rotation_did_key
+            ),
+            "success",
+        )?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn create_plc(self: &Rc<Self>) -> Result<()> {
+        let handle = self.input("e5a0c7d2-4f19-4b3a-9c86-1d7e2f5b8a40")?;
+        let pds = self.input("b8c4f1e6-7a25-4d93-8e0b-3f6a9d2c1e57")?;
+        let directory = self.input("2f9e6b1d-c853-47a0-b4e2-8d1c5a7f3e69")?;
+
+        let keys = self.authenticate().await?;
+
+        let rotation_key = didkey::encode(keys.rotation.algorithm(), &keys.rotation.public_key())?;
+        let op = Operation::genesis(&rotation_key, &keys.signer.did_key()?, &handle, &pds)?
+            .sign(&keys.rotation)?;
+
+        info!(op = ?op, "Signed plc genesis operation");
+
+        let did = PlcDirectory::new(&directory).create(&op, plc::post).await?;
+
+        self.update_status(&format!("Created {did}"), "success")?;
+
+        Ok(())
+    }
+
+    /// Signs the data with the derived signing key, returning an object with
+    /// the raw and (for ECDSA) DER signatures plus the public key.
+    pub(crate) async fn sign(self: &Rc<Self>, data: &[u8]) -> Result<JsValue> {
+        let signer = self.signer().await?;
+        let algorithm = signer.algorithm();
+
+        let public_key = signer.public_key();
+        let signature = signer.sign(data).await?;
+        let der = algorithm.der_signature(&signature)?;
+
+        let res = Object::new();
+        res.set("algorithm", &algorithm.as_str().into())?;
+        res.set("didKey", &didkey::encode(algorithm, &public_key)?.into())?;
+        res.set("publicKey", &Uint8Array::from(public_key.as_slice()).into())?;
+        res.set("signature", &Uint8Array::from(signature.as_slice()).into())?;
+        res.set(
+            "der",
+            &der.map_or(JsValue::NULL, |der| Uint8Array::from(der.as_slice()).into()),
+        )?;
+
+        Ok(res.into())
+    }
+
+    /// Signs the claims object as a compact JWT with the derived signing key.
+    pub(crate) async fn sign_jwt(self: &Rc<Self>, claims: JsValue) -> Result<String> {
+        let claims = serde_wasm_bindgen::from_value(claims)
+            .map_err(|_| Error::new("failed to convert jwt claims"))?;
+
+        let signer = self.signer().await?;
+
+        let signing_input = jws::signing_input(signer.algorithm(), &signer.public_key(), &claims)?;
+        let signature = signer.sign(signing_input.as_bytes()).await?;
+
+        Ok(jws::compact(&signing_input, &signature))
+    }
+
+    /// Returns the cached signing key, running a login to derive it if there
+    /// is none yet.
+    async fn signer(self: &Rc<Self>) -> Result<Rc<Signer>> {
+        if self.signer.borrow().is_none() {
+            self.abort_conditional();
+
+            if let Err(err) = self.authenticate().await {
+                self.start_conditional_login();
+                return Err(err);
+            }
+        }
+
+        let signer = self
+            .signer
+            .borrow()
+            .clone()
+            .context("no signing key after login")?;
+
+        Ok(signer)
+    }
+
+    /// Runs the authentication ceremony and derives the keys from its PRF.
+    /// Without a DID, any discoverable credential can log in.
+    async fn authenticate(self: &Rc<Self>) -> Result<LoginKeys> {
         let did = self
             .document
             .id("dceaf2f7-75b8-4e61-88d0-99d32797af8b")?
             .cast::<HtmlInputElement>()?
             .value();
 
+        if did.is_empty() {
+            return self.authenticate_discoverable(None).await;
+        }
+
         info!(did = %did, "Login started");
 
         let res = self
@@ -130,12 +408,129 @@ impl Application {
             .await?;
 
         let challenge = res.challenge;
+        let challenge_bytes = challenge.public_key.challenge.to_vec();
+        let derivations = res.derivations;
 
         info!(challenge = ?challenge, "Got login challenge");
 
+        let (credential, prf) = self.get_credential(challenge, None).await?;
+
+        // Keys have to be derived with the scheme the credential was registered with
+        let derivation = derivations
+            .iter()
+            .find(|derivation| derivation.credential_id == credential.raw_id)
+            .context("no derivation version for credential")?;
+        let version = derivation.derivation;
+        let algorithm = self.recorded_algorithm(derivation.algorithm)?;
+
+        let keys = self.derive_keys(&prf, &did, version, algorithm).await?;
+
+        // Lets the server detect a key that differs from the registered one
+        let signing_key = Self::signer_proof(&keys.signer, &did, &challenge_bytes).await?;
+
+        self.client
+            .auth_finish(FinishAuthenticationRequest {
+                credential,
+                signing_key,
+            })
+            .await?;
+
+        self.signer.replace(Some(keys.signer.clone()));
+
+        Ok(keys)
+    }
+
+    /// Runs a usernameless authentication ceremony with a discoverable
+    /// credential, deriving the keys for the DID the server resolves from it.
+    /// With a signal, the credentials are offered in the autofill of the DID
+    /// input (conditional mediation) until it is aborted.
+    async fn authenticate_discoverable(
+        self: &Rc<Self>,
+        conditional: Option<&AbortSignal>,
+    ) -> Result<LoginKeys> {
+        info!("Usernameless login started");
+
+        let res = self
+            .client
+            .auth_discover_start(StartDiscoverableAuthenticationRequest {
+                conditional: conditional.is_some(),
+            })
+            .await?;
+
+        // A button may have started its own ceremony while this was in flight
+        if conditional.is_some_and(AbortSignal::aborted) {
+            return Err(Error::new("autofill aborted"));
+        }
+
+        let mut challenge = res.challenge;
+
+        // Autofill stays open for as long as the page, so it is re-armed with
+        // a fresh challenge before this one expires, rather than failing the
+        // user's pick. The timer is cancelled once a credential is picked.
+        let rearm = conditional.map(|_| {
+            let webauthn = self.clone();
+            let millis = res.expires_in_secs.saturating_mul(REARM_PERMILLE).max(1000);
+
+            Timeout::new(u32::try_from(millis).unwrap_or(u32::MAX), move || {
+                info!("Re-arming autofill before its challenge expires");
+                webauthn.start_conditional_login();
+            })
+        });
+
+        if conditional.is_some() {
+            challenge.mediation = Some(Mediation::Conditional);
+        }
+
+        let challenge_bytes = challenge.public_key.challenge.to_vec();
+
+        info!(challenge = ?challenge, "Got login challenge");
+
+        let (credential, prf) = self.get_credential(challenge, conditional).await?;
+        drop(rearm);
+
+        let res = self
+            .client
+            .auth_discover_finish(FinishDiscoverableAuthenticationRequest { credential })
+            .await?;
+
+        info!(did = %res.did, "Resolved user from credential");
+
+        let did = res.did;
+        let algorithm = self.recorded_algorithm(res.algorithm)?;
+        let keys = self
+            .derive_keys(&prf, &did, res.derivation, algorithm)
+            .await?;
+
+        // Only logs in once the server has checked the key for the DID
+        let signing_key = Self::signer_proof(&keys.signer, &did, &challenge_bytes).await?;
+
+        self.client
+            .auth_discover_verify(VerifyDiscoverableAuthenticationRequest { signing_key })
+            .await?;
+
+        self.document
+            .id("dceaf2f7-75b8-4e61-88d0-99d32797af8b")?
+            .cast::<HtmlInputElement>()?
+            .set_value(&did);
+
+        self.signer.replace(Some(keys.signer.clone()));
+
+        Ok(keys)
+    }
+
+    /// Gets an assertion for the challenge with the PRF evaluated.
+    async fn get_credential(
+        &self,
+        challenge: RequestChallengeResponse,
+        signal: Option<&AbortSignal>,
+    ) -> Result<(webauthn_rs_proto::PublicKeyCredential, Secret)> {
         let options: CredentialRequestOptions = challenge.into();
         options.set_prf_first(b"".as_ref())?;
 
+        if let Some(signal) = signal {
+            options.set_signal(signal);
+        }
+
         info!(options = ?options, "Got login options");
 
         let promise = self
@@ -152,25 +547,51 @@ impl Application {
         let credential = PublicKeyCredential::from(credential);
         let prf = credential.get_prf_first()?;
 
-        info!(credential = ?credential, prf = ?prf, "Created credential, finishing login");
+        info!(credential = ?credential, "Created credential, finishing login");
 
-        let keypair = Keygen::new().generate(&prf, did.as_bytes()).unwrap();
+        Ok((credential.into(), prf))
+    }
 
-        let pk = keypair.public_key().as_ref();
-        let pk_multibase = multibase::encode(multibase::Base::Base58Btc, pk);
+    /// Derives the keys of a login. With the WebCrypto option, the signing key
+    /// is only derived as PKCS#8 for the import, never as keypair in WASM
+    /// memory.
+    async fn derive_keys(
+        &self,
+        prf: &[u8],
+        did: &str,
+        version: Version,
+        algorithm: Algorithm,
+    ) -> Result<LoginKeys> {
+        let keygen = Keygen::new(version);
+
+        let signer = if self.webcrypto()? {
+            if algorithm != Algorithm::P256 {
+                return Err(Error::new("WebCrypto keys require P-256"));
+            }
 
-        info!(public_key = ?pk, public_key_multibase = %pk_multibase, "Generated keypair for login");
+            // The PKCS#8 document is zeroized when dropped after the import
+            let (pkcs8, public_key) = keygen.generate_p256_pkcs8(prf, did.as_bytes())?;
+            let subtle = self.window.crypto().map_err(Error::from_js_value)?.subtle();
 
-        self.client
-            .auth_finish(FinishAuthenticationRequest {
-                credential: credential.into(),
-            })
-            .await?;
+            Signer::import(subtle, pkcs8.as_bytes(), public_key).await?
+        } else {
+            Signer::Memory(keygen.generate(prf, did.as_bytes(), algorithm)?)
+        };
 
-        self.update_status(
-            &format!("Login successful - public key: {}", pk_multibase),
-            "success",
-        )?;
+        Ok(LoginKeys {
+            signer: Rc::new(signer),
+            rotation: keygen.generate_rotation(prf, did.as_bytes(), algorithm)?,
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn logout_user(self: &Rc<Self>) -> Result<()> {
+        self.client.auth_logout().await?;
+        self.signer.take();
+
+        self.update_status("Logged out", "success")?;
+
+        self.start_conditional_login();
 
         Ok(())
     }
@@ -191,6 +612,7 @@ impl Application {
             .await?;
 
         let challenge = res.challenge;
+        let challenge_bytes = challenge.public_key.challenge.to_vec();
 
         info!(challenge = ?challenge, "Got registration challenge");
 
@@ -213,30 +635,144 @@ impl Application {
         let credential = PublicKeyCredential::from(credential);
         let prf = credential.get_prf_first()?;
 
-        let keypair = Keygen::new().generate(&prf, did.as_bytes()).unwrap();
+        let keys =
+            Keygen::new(Version::LATEST).generate_keys(&prf, did.as_bytes(), self.algorithm()?)?;
+
+        // Proves to the server that the keys were derived by this ceremony
+        let signing_key = Self::key_proof(&keys.signing, &did, &challenge_bytes)?;
+        let rotation_key = Self::key_proof(&keys.rotation, &did, &challenge_bytes)?;
 
-        let pk = keypair.public_key().as_ref();
-        let pk_multibase = multibase::encode(multibase::Base::Base58Btc, pk);
+        let did_key = signing_key.did_key.clone();
 
         self.client
             .register_finish(FinishRegistrationRequest {
                 credential: credential.into(),
+                derivation: Version::LATEST,
+                signing_key,
+                rotation_key,
             })
             .await?;
 
         self.update_status(
-            &format!("Registration successful - public key: {}", pk_multibase),
+            &format!("Registration successful - public key: {}", did_key),
             "success",
         )?;
 
         Ok(())
     }
 
+    #[instrument(skip(self))]
+    async fn add_credential(self: &Rc<Self>) -> Result<()> {
+        // The key is bound to the logged in user's DID, whatever the input says
+        let did = self.client.auth_me().await?.did;
+
+        info!(did = %did, "Adding credential");
+
+        let res = self.client.credential_add_start().await?;
+
+        let challenge = res.challenge;
+        let challenge_bytes = challenge.public_key.challenge.to_vec();
+
+        info!(challenge = ?challenge, "Got credential challenge");
+
+        let options: CredentialCreationOptions = challenge.into();
+        options.set_prf_first(b"".as_ref())?;
+
+        let promise = self
+            .window
+            .navigator()
+            .credentials()
+            .create_with_options(&options)
+            .map_err(Error::from_js_value)?;
+
+        let credential = JsFuture::from(promise)
+            .await
+            .map_err(Error::from_js_value)?;
+
+        let credential = PublicKeyCredential::from(credential);
+        let prf = credential.get_prf_first()?;
+
+        // Every authenticator has its own PRF secret and hence yields its own key
+        let keypair =
+            Keygen::new(Version::LATEST).generate(&prf, did.as_bytes(), self.algorithm()?)?;
+
+        let signing_key = Self::key_proof(&keypair, &did, &challenge_bytes)?;
+        let did_key = signing_key.did_key.clone();
+
+        self.client
+            .credential_add_finish(FinishAddCredentialRequest {
+                credential: credential.into(),
+                derivation: Version::LATEST,
+                signing_key,
+            })
+            .await?;
+
+        self.update_status(
+            &format!("Device added - public key: {}", did_key),
+            "success",
+        )?;
+
+        Ok(())
+    }
+
+    fn key_proof(keypair: &Keypair, did: &str, challenge: &[u8]) -> Result<KeyProof> {
+        Ok(KeyProof {
+            did_key: didkey::encode(keypair.algorithm(), &keypair.public_key())?,
+            signature: proof::sign(keypair, did, challenge)?.into(),
+        })
+    }
+
+    /// Like [`Self::key_proof`], for a signing key that may only exist in
+    /// WebCrypto.
+    async fn signer_proof(signer: &Signer, did: &str, challenge: &[u8]) -> Result<KeyProof> {
+        Ok(KeyProof {
+            did_key: signer.did_key()?,
+            signature: signer.sign(&proof::message(did, challenge)?).await?.into(),
+        })
+    }
+
+    fn input(&self, id: &str) -> Result<String> {
+        let value = self.document.id(id)?.cast::<HtmlInputElement>()?.value();
+        Ok(value)
+    }
+
+    /// Whether the signing key is to be imported into WebCrypto as
+    /// non-extractable key, instead of being kept in WASM memory.
+    fn webcrypto(&self) -> Result<bool> {
+        let checked = self
+            .document
+            .id("6b3f8e2a-9d41-4c75-a1e0-5f7c2b8d9e34")?
+            .cast::<HtmlInputElement>()?
+            .checked();
+
+        Ok(checked)
+    }
+
+    /// The algorithm the server recorded for the credential, or the selected
+    /// one for credentials from before it was recorded.
+    fn recorded_algorithm(&self, recorded: Option<Algorithm>) -> Result<Algorithm> {
+        match recorded {
+            Some(algorithm) => Ok(algorithm),
+            None => self.algorithm(),
+        }
+    }
+
+    fn algorithm(&self) -> Result<Algorithm> {
+        let algorithm = self
+            .document
+            .id("7d2b9e4f-1a83-4c6e-b05f-e8c3a2d91f47")?
+            .cast::<HtmlSelectElement>()?
+            .value()
+            .parse()?;
+
+        Ok(algorithm)
+    }
+
     fn update_status(self: &Rc<Self>, msg: &str, status_type: &str) -> Result<()> {
         let div = self.document.id("d4e5f6g7-h8i9-0123-def0-234567890123")?;
 
         div.set_class_name(&format!("status {status_type}"));
-        div.set_inner_html(msg);
+        div.set_text_content(Some(msg));
 
         Ok(())
     }
@@ -1,12 +1,44 @@
 use api::*;
-use gloo_net::http::Request;
+use gloo_net::http::{Method, RequestBuilder, Response};
+use gloo_timers::callback::Timeout;
+use gloo_timers::future::TimeoutFuture;
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::warn;
+use web_sys::{AbortController, AbortSignal};
 
-use crate::error::{Context, Result};
+use crate::error::{Context, Error, Result};
+
+/// Milliseconds after which a request is aborted.
+const TIMEOUT_MS: u32 = 10_000;
+
+/// Attempts for requests that are safe to repeat.
+const RETRY_ATTEMPTS: u32 = 3;
+
+/// Milliseconds to wait before a retry, multiplied by the attempt.
+const RETRY_DELAY_MS: u32 = 500;
+
+/// Body of requests that send none.
+const NO_BODY: Option<&()> = None;
 
 pub struct Client {
     endpoint: String,
 }
 
+/// A failed request, with the HTTP status if the server responded.
+struct Failure {
+    status: Option<u16>,
+    err: Error,
+}
+
+/// Whether a request can be sent again after a timeout or server error.
+#[derive(Clone, Copy, Debug)]
+enum Retry {
+    /// Reads, and starting a ceremony again only replaces the challenge in the
+    /// session.
+    Idempotent,
+    Never,
+}
+
 impl Client {
     pub fn new(endpoint: &str) -> Self {
         Client {
@@ -18,78 +50,221 @@ impl Client {
         &self,
         req: StartAuthenticationRequest,
     ) -> Result<StartAuthenticationResponse> {
-        let req = Request::post(&self.url("auth/start"))
-            .json(&req)
-            .context("failed to serialize authentication start request")?
-            .send()
-            .await
-            .context("failed to send authentication start request")?;
-
-        let res = req
-            .json()
-            .await
-            .context("failed to parse authentication start response")?;
-
-        Ok(res)
+        self.post("auth/start", Some(&req), Retry::Idempotent).await
     }
 
     pub async fn auth_finish(
         &self,
         req: FinishAuthenticationRequest,
     ) -> Result<FinishAuthenticationResponse> {
-        let req = Request::post(&self.url("auth/finish"))
-            .json(&req)
-            .context("failed to serialize authentication finish request")?
-            .send()
+        self.post("auth/finish", Some(&req), Retry::Never).await
+    }
+
+    pub async fn auth_discover_start(
+        &self,
+        req: StartDiscoverableAuthenticationRequest,
+    ) -> Result<StartDiscoverableAuthenticationResponse> {
+        self.post("auth/discover/start", Some(&req), Retry::Idempotent)
             .await
-            .context("failed to send authentication finish request")?;
+    }
 
-        let res = req
-            .json()
+    pub async fn auth_discover_finish(
+        &self,
+        req: FinishDiscoverableAuthenticationRequest,
+    ) -> Result<FinishDiscoverableAuthenticationResponse> {
+        self.post("auth/discover/finish", Some(&req), Retry::Never)
             .await
-            .context("failed to parse authentication finish response")?;
+    }
 
-        Ok(res)
+    pub async fn auth_discover_verify(
+        &self,
+        req: VerifyDiscoverableAuthenticationRequest,
+    ) -> Result<VerifyDiscoverableAuthenticationResponse> {
+        self.post("auth/discover/verify", Some(&req), Retry::Never)
+            .await
+    }
+
+    pub async fn auth_me(&self) -> Result<MeResponse> {
+        self.get("auth/me", Retry::Idempotent).await
+    }
+
+    pub async fn auth_logout(&self) -> Result<LogoutResponse> {
+        self.post("auth/logout", NO_BODY, Retry::Never).await
     }
 
     pub async fn register_start(
         &self,
         req: StartRegistrationRequest,
     ) -> Result<StartRegistrationResponse> {
-        let req = Request::post(&self.url("register/start"))
-            .json(&req)
-            .context("failed to serialize registration start request")?
-            .send()
+        self.post("register/start", Some(&req), Retry::Idempotent)
             .await
-            .context("failed to send registration start request")?;
-
-        let res = req
-            .json()
-            .await
-            .context("failed to parse registration start response")?;
-
-        Ok(res)
     }
 
     pub async fn register_finish(
         &self,
         req: FinishRegistrationRequest,
     ) -> Result<FinishRegistrationResponse> {
-        let req = Request::post(&self.url("register/finish"))
-            .json(&req)
-            .context("failed to serialize registration finish request")?
-            .send()
+        self.post("register/finish", Some(&req), Retry::Never).await
+    }
+
+    pub async fn credential_add_start(&self) -> Result<StartAddCredentialResponse> {
+        self.post("credentials/add/start", NO_BODY, Retry::Idempotent)
+            .await
+    }
+
+    pub async fn credential_add_finish(
+        &self,
+        req: FinishAddCredentialRequest,
+    ) -> Result<FinishAddCredentialResponse> {
+        self.post("credentials/add/finish", Some(&req), Retry::Never)
             .await
-            .context("failed to send registration finish request")?;
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, retry: Retry) -> Result<T> {
+        self.send(Method::GET, path, NO_BODY, retry).await
+    }
+
+    async fn post<B, T>(&self, path: &str, body: Option<&B>, retry: Retry) -> Result<T>
+    where
+        B: Serialize,
+        T: DeserializeOwned,
+    {
+        self.send(Method::POST, path, body, retry).await
+    }
+
+    /// Sends a JSON request and parses its response, retrying idempotent
+    /// requests on timeouts, network and server errors.
+    async fn send<B, T>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        retry: Retry,
+    ) -> Result<T>
+    where
+        B: Serialize,
+        T: DeserializeOwned,
+    {
+        let attempts = match retry {
+            Retry::Idempotent => RETRY_ATTEMPTS,
+            Retry::Never => 1,
+        };
+
+        let mut attempt = 1;
+
+        loop {
+            let Failure { status, err } = match self.attempt(method.clone(), path, body).await {
+                Ok(res) => return Ok(res),
+                Err(failure) => failure,
+            };
+
+            // Client errors won't go away by sending the same request again,
+            // whatever their body says
+            let retryable = status.is_none_or(|status| status >= 500);
+
+            if !retryable || attempt >= attempts {
+                return Err(err);
+            }
+
+            warn!(err = ?err, path, attempt, "request failed, retrying");
+
+            TimeoutFuture::new(RETRY_DELAY_MS * attempt).await;
+            attempt += 1;
+        }
+    }
+
+    async fn attempt<B, T>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> std::result::Result<T, Failure>
+    where
+        B: Serialize,
+        T: DeserializeOwned,
+    {
+        let controller = AbortController::new().map_err(|err| Failure {
+            status: None,
+            err: Error::from_js_value(err),
+        })?;
+        let signal = controller.signal();
+
+        // Cancelled when dropped, once the response is read
+        let _timeout = Timeout::new(TIMEOUT_MS, move || controller.abort());
+
+        let res = self
+            .request(method, path, body, &signal)
+            .await
+            .map_err(|err| Failure { status: None, err })?;
+
+        let status = res.status();
+
+        Self::parse(res, path).await.map_err(|err| Failure {
+            status: Some(status),
+            err,
+        })
+    }
+
+    /// Sends the request, failing without a response on timeouts and network
+    /// errors.
+    async fn request<B>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        signal: &AbortSignal,
+    ) -> Result<Response>
+    where
+        B: Serialize,
+    {
+        let req = RequestBuilder::new(&self.url(path))
+            .method(method)
+            .abort_signal(Some(signal));
+
+        let req = match body {
+            Some(body) => req
+                .json(body)
+                .context(format!("failed to serialize {path} request"))?,
+            None => req
+                .build()
+                .context(format!("failed to build {path} request"))?,
+        };
 
         let res = req
+            .send()
+            .await
+            .context(format!("failed to send {path} request"))?;
+
+        Ok(res)
+    }
+
+    async fn parse<T: DeserializeOwned>(res: Response, path: &str) -> Result<T> {
+        let res = Self::check(res).await?;
+
+        let res = res
             .json()
             .await
-            .context("failed to parse registration finish response")?;
+            .context(format!("failed to parse {path} response"))?;
 
         Ok(res)
     }
 
+    /// Turns error responses into [`ErrorResponse`] errors, see
+    /// [`Error::api`](crate::error::Error::api).
+    async fn check(res: Response) -> Result<Response> {
+        if res.ok() {
+            return Ok(res);
+        }
+
+        let status = res.status();
+        let err: ErrorResponse = res
+            .json()
+            .await
+            .context(format!("request failed with {status}"))?;
+
+        Err(err.into())
+    }
+
     fn url(&self, path: &str) -> String {
         format!("https://{}/{}", self.endpoint, path)
     }
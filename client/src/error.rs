@@ -1,3 +1,4 @@
+use api::ErrorResponse;
 use wasm_bindgen::JsValue;
 
 pub(crate) use anyhow::Context;
@@ -14,6 +15,11 @@ impl Error {
         let msg = format!("{msg:?}");
         Error(anyhow::anyhow!(msg))
     }
+
+    /// The server's error, if the request was rejected by the API.
+    pub(crate) fn api(&self) -> Option<&ErrorResponse> {
+        self.0.downcast_ref()
+    }
 }
 
 impl<E> From<E> for Error
@@ -1,6 +1,10 @@
 use std::rc::Rc;
 
+use js_sys::Promise;
+use keygen::didkey;
+use serde::Serialize;
 use wasm_bindgen::{JsValue, prelude::wasm_bindgen};
+use wasm_bindgen_futures::future_to_promise;
 use web_sys::window;
 
 use crate::app::Application;
@@ -9,9 +13,60 @@ use crate::error::{Error, Result};
 mod app;
 mod client;
 mod error;
-mod keygen;
+mod plc;
+mod signer;
 mod util;
 
+/// Decodes a `did:key` into `{ algorithm, publicKey }`, with the public key
+/// in compressed form.
+#[wasm_bindgen(js_name = decodeDidKey)]
+pub fn decode_did_key(did: &str) -> Result<JsValue> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct DidKey {
+        algorithm: &'static str,
+        public_key: Vec<u8>,
+    }
+
+    let (algorithm, public_key) = didkey::decode(did)?;
+
+    let did_key = DidKey {
+        algorithm: algorithm.as_str(),
+        public_key,
+    };
+
+    serde_wasm_bindgen::to_value(&did_key).map_err(|_| Error::new("failed to convert did:key"))
+}
+
+#[wasm_bindgen]
+pub struct ApplicationRc(Rc<Application>);
+
+#[wasm_bindgen]
+impl ApplicationRc {
+    /// Signs the bytes with the derived signing key, prompting for a passkey
+    /// login if no key is cached. Resolves to `{ algorithm, didKey, publicKey,
+    /// signature, der }`, where `der` is null for Ed25519.
+    pub fn sign(&self, data: Vec<u8>) -> Promise {
+        let app = self.0.clone();
+        future_to_promise(async move { Ok(app.sign(&data).await?) })
+    }
+
+    /// Signs the UTF-8 encoding of the string, see `sign`.
+    #[wasm_bindgen(js_name = signString)]
+    pub fn sign_string(&self, data: String) -> Promise {
+        let app = self.0.clone();
+        future_to_promise(async move { Ok(app.sign(data.as_bytes()).await?) })
+    }
+
+    /// Signs the claims object as a compact JWT (`ES256`, `ES256K` or `EdDSA`)
+    /// with the `did:key` verification method as `kid`.
+    #[wasm_bindgen(js_name = signJwt)]
+    pub fn sign_jwt(&self, claims: JsValue) -> Promise {
+        let app = self.0.clone();
+        future_to_promise(async move { Ok(app.sign_jwt(claims).await?.into()) })
+    }
+}
+
 #[wasm_bindgen]
 pub async fn init() -> Result<JsValue> {
     #[cfg(feature = "console_error_panic_hook")]
@@ -25,8 +80,7 @@ pub async fn init() -> Result<JsValue> {
         return Err(Error::new("prf support is required"));
     }
 
-    #[wasm_bindgen]
-    struct ApplicationRc(Rc<Application>);
+    webauthn.start_conditional_login();
 
     Ok(ApplicationRc(webauthn).into())
 }
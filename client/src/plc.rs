@@ -0,0 +1,15 @@
+use anyhow::{Context, Result};
+use gloo_net::http::Request;
+use serde_json::Value;
+
+/// Sends a PLC operation for [`keygen::plc::PlcDirectory::create`].
+pub(crate) async fn post(url: String, body: Value) -> Result<(u16, String)> {
+    let res = Request::post(&url)
+        .json(&body)
+        .context("failed to serialize plc operation")?
+        .send()
+        .await
+        .context("failed to send plc operation")?;
+
+    Ok((res.status(), res.text().await.unwrap_or_default()))
+}
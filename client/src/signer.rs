@@ -0,0 +1,105 @@
+use std::rc::Rc;
+
+use js_sys::{Array, Uint8Array};
+use keygen::{Algorithm, Keypair, didkey};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{CryptoKey, EcKeyImportParams, EcdsaParams, SubtleCrypto};
+
+use crate::error::{Error, Result};
+use crate::util::ValueExt;
+
+/// The keys derived by a login. The signing key is only used through its
+/// signer, as it may not exist in WASM memory.
+pub(crate) struct LoginKeys {
+    pub(crate) signer: Rc<Signer>,
+    pub(crate) rotation: Keypair,
+}
+
+/// The derived signing key, either held in WASM memory or imported into
+/// WebCrypto.
+pub(crate) enum Signer {
+    Memory(Keypair),
+    WebCrypto {
+        subtle: SubtleCrypto,
+        key: CryptoKey,
+        public_key: Vec<u8>,
+    },
+}
+
+impl Signer {
+    /// Imports a P-256 PKCS#8 key as non-extractable WebCrypto key. The JS
+    /// copy of the key is overwritten once imported; the caller is
+    /// responsible for zeroizing `pkcs8`.
+    pub(crate) async fn import(
+        subtle: SubtleCrypto,
+        pkcs8: &[u8],
+        public_key: Vec<u8>,
+    ) -> Result<Self> {
+        let data = Uint8Array::from(pkcs8);
+
+        let params = EcKeyImportParams::new("ECDSA");
+        params.set_named_curve("P-256");
+
+        let usages = Array::of1(&JsValue::from_str("sign"));
+
+        let imported = subtle
+            .import_key_with_object("pkcs8", &data, &params, false, &usages)
+            .map_err(Error::from_js_value);
+
+        data.fill(0, 0, data.length());
+
+        let key = JsFuture::from(imported?)
+            .await
+            .map_err(Error::from_js_value)?;
+
+        let key = key.cast::<CryptoKey>()?.clone();
+
+        Ok(Self::WebCrypto {
+            subtle,
+            key,
+            public_key,
+        })
+    }
+
+    pub(crate) fn algorithm(&self) -> Algorithm {
+        match self {
+            Self::Memory(keypair) => keypair.algorithm(),
+            Self::WebCrypto { .. } => Algorithm::P256,
+        }
+    }
+
+    pub(crate) fn public_key(&self) -> Vec<u8> {
+        match self {
+            Self::Memory(keypair) => keypair.public_key(),
+            Self::WebCrypto { public_key, .. } => public_key.clone(),
+        }
+    }
+
+    pub(crate) fn did_key(&self) -> Result<String> {
+        Ok(didkey::encode(self.algorithm(), &self.public_key())?)
+    }
+
+    /// Signs like [`keygen::Keypair::sign`], with a low S `r || s` ECDSA
+    /// signature.
+    pub(crate) async fn sign(&self, msg: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Memory(keypair) => Ok(keypair.sign(msg)?),
+            Self::WebCrypto { subtle, key, .. } => {
+                let params = EcdsaParams::new("ECDSA", &JsValue::from_str("SHA-256"));
+
+                let promise = subtle
+                    .sign_with_object_and_u8_array(&params, key, msg)
+                    .map_err(Error::from_js_value)?;
+
+                let signature = JsFuture::from(promise)
+                    .await
+                    .map_err(Error::from_js_value)?;
+
+                let signature = Uint8Array::new(&signature).to_vec();
+
+                Ok(Algorithm::P256.normalize_signature(&signature)?)
+            }
+        }
+    }
+}
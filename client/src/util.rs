@@ -1,4 +1,5 @@
 use js_sys::{ArrayBuffer, Function, Object, Promise, Reflect, Uint8Array};
+use keygen::Secret;
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{
@@ -67,11 +68,12 @@ impl CredentialOptionsExt for CredentialRequestOptions {
 }
 
 pub trait PublicKeyCredentialExt {
-    fn get_prf_first(&self) -> Result<Vec<u8>>;
+    fn get_prf_first(&self) -> Result<Secret>;
 }
 
 impl PublicKeyCredentialExt for PublicKeyCredential {
-    fn get_prf_first(&self) -> Result<Vec<u8>> {
+    /// Copies the PRF output into zeroizing memory and overwrites the JS copy.
+    fn get_prf_first(&self) -> Result<Secret> {
         let prf = self
             .get_client_extension_results()
             .get("prf")?
@@ -79,7 +81,10 @@ impl PublicKeyCredentialExt for PublicKeyCredential {
             .get("first")?;
 
         let prf = prf.cast::<ArrayBuffer>()?;
-        let prf = Uint8Array::new(prf).to_vec();
+        let view = Uint8Array::new(prf);
+        let prf = Secret::new(view.to_vec());
+
+        view.fill(0, 0, view.length());
 
         Ok(prf)
     }
@@ -0,0 +1,117 @@
+use anyhow::{Context, Result, anyhow, bail};
+use k256::{ecdsa::signature::Verifier, elliptic_curve::sec1::ToEncodedPoint};
+use ring::signature::{ED25519, UnparsedPublicKey};
+
+use crate::Algorithm;
+
+pub(crate) const DID_KEY_PREFIX: &str = "did:key:";
+
+// Unsigned varint encodings of the multicodec public key codes
+const P256_PUB: &[u8] = &[0x80, 0x24];
+const SECP256K1_PUB: &[u8] = &[0xe7, 0x01];
+const ED25519_PUB: &[u8] = &[0xed, 0x01];
+
+const ED25519_KEYLEN: usize = 32;
+
+impl Algorithm {
+    fn multicodec(&self) -> &'static [u8] {
+        match self {
+            Self::P256 => P256_PUB,
+            Self::Secp256k1 => SECP256K1_PUB,
+            Self::Ed25519 => ED25519_PUB,
+        }
+    }
+}
+
+/// Encodes a public key (SEC1 for ECDSA keys, raw for Ed25519) as a
+/// `did:key`, compressing ECDSA points first.
+pub fn encode(algorithm: Algorithm, public_key: &[u8]) -> Result<String> {
+    let key = compress(algorithm, public_key)?;
+    let bytes = [algorithm.multicodec(), &key].concat();
+
+    Ok(format!(
+        "{DID_KEY_PREFIX}{}",
+        multibase::encode(multibase::Base::Base58Btc, bytes)
+    ))
+}
+
+/// Decodes a `did:key` into its algorithm and the compressed public key.
+pub fn decode(did: &str) -> Result<(Algorithm, Vec<u8>)> {
+    let encoded = did.strip_prefix(DID_KEY_PREFIX).context("not a did:key")?;
+
+    let (base, bytes) = multibase::decode(encoded).context("malformed multibase")?;
+
+    if base != multibase::Base::Base58Btc {
+        bail!("did:key must be base58btc encoded");
+    }
+
+    let algorithm = [Algorithm::P256, Algorithm::Secp256k1, Algorithm::Ed25519]
+        .into_iter()
+        .find(|algorithm| bytes.starts_with(algorithm.multicodec()))
+        .context("unsupported multicodec")?;
+
+    let key = &bytes[algorithm.multicodec().len()..];
+
+    // Round trip through compression to validate the point
+    let compressed = compress(algorithm, key)?;
+
+    if compressed != key {
+        bail!("did:key public key is not compressed");
+    }
+
+    Ok((algorithm, compressed))
+}
+
+/// Verifies a signature as produced by [`crate::Keypair::sign`] against the
+/// public key of a `did:key`.
+pub fn verify(did: &str, msg: &[u8], signature: &[u8]) -> Result<()> {
+    let (algorithm, key) = decode(did)?;
+
+    match algorithm {
+        Algorithm::P256 => {
+            let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&key)
+                .context("invalid p256 public key")?;
+            let signature =
+                p256::ecdsa::Signature::from_slice(signature).context("malformed signature")?;
+
+            key.verify(msg, &signature).context("invalid signature")?;
+        }
+        Algorithm::Secp256k1 => {
+            let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(&key)
+                .context("invalid secp256k1 public key")?;
+            let signature =
+                k256::ecdsa::Signature::from_slice(signature).context("malformed signature")?;
+
+            key.verify(msg, &signature).context("invalid signature")?;
+        }
+        Algorithm::Ed25519 => UnparsedPublicKey::new(&ED25519, key)
+            .verify(msg, signature)
+            .map_err(|_| anyhow!("invalid signature"))?,
+    }
+
+    Ok(())
+}
+
+fn compress(algorithm: Algorithm, public_key: &[u8]) -> Result<Vec<u8>> {
+    let key = match algorithm {
+        Algorithm::P256 => p256::PublicKey::from_sec1_bytes(public_key)
+            .context("invalid p256 public key")?
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec(),
+        Algorithm::Secp256k1 => k256::PublicKey::from_sec1_bytes(public_key)
+            .context("invalid secp256k1 public key")?
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec(),
+        Algorithm::Ed25519 => {
+            if public_key.len() != ED25519_KEYLEN {
+                bail!("invalid ed25519 public key");
+            }
+
+            public_key.to_vec()
+        }
+    };
+
+    Ok(key)
+}
@@ -0,0 +1,149 @@
+//! Compact JWS / JWT tokens signed with a derived key, identified by its
+//! `did:key` verification method in the `kid` header.
+
+use anyhow::{Context, Result, bail};
+use data_encoding::BASE64URL_NOPAD;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Algorithm, Keypair, didkey};
+
+const JWT: &str = "JWT";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub alg: String,
+    pub typ: String,
+    pub kid: String,
+}
+
+/// A token that passed [`verify`].
+#[derive(Clone, Debug)]
+pub struct Verified {
+    pub header: Header,
+    pub claims: Value,
+}
+
+impl Algorithm {
+    /// The JOSE `alg` name.
+    pub fn jws_alg(&self) -> &'static str {
+        match self {
+            Self::P256 => "ES256",
+            Self::Secp256k1 => "ES256K",
+            Self::Ed25519 => "EdDSA",
+        }
+    }
+}
+
+/// The `did:key` verification method, `did:key:z...#z...`.
+pub fn verification_method(did_key: &str) -> Result<String> {
+    let fragment = did_key
+        .strip_prefix(didkey::DID_KEY_PREFIX)
+        .context("not a did:key")?;
+
+    Ok(format!("{did_key}#{fragment}"))
+}
+
+/// Signs the claims as a compact JWT.
+pub fn sign(keypair: &Keypair, claims: &Value) -> Result<String> {
+    let signing_input = signing_input(keypair.algorithm(), &keypair.public_key(), claims)?;
+    let signature = keypair.sign(signing_input.as_bytes())?;
+
+    Ok(compact(&signing_input, &signature))
+}
+
+/// The encoded header and claims to be signed, for signers other than a
+/// [`Keypair`]; see [`compact`].
+pub fn signing_input(algorithm: Algorithm, public_key: &[u8], claims: &Value) -> Result<String> {
+    if !claims.is_object() {
+        bail!("jwt claims must be an object");
+    }
+
+    let did_key = didkey::encode(algorithm, public_key)?;
+
+    let header = Header {
+        alg: algorithm.jws_alg().to_owned(),
+        typ: JWT.to_owned(),
+        kid: verification_method(&did_key)?,
+    };
+
+    Ok(format!(
+        "{}.{}",
+        BASE64URL_NOPAD.encode(&serde_json::to_vec(&header)?),
+        BASE64URL_NOPAD.encode(&serde_json::to_vec(claims)?)
+    ))
+}
+
+/// Appends the `r || s` (or Ed25519) signature to the signing input.
+pub fn compact(signing_input: &str, signature: &[u8]) -> String {
+    format!("{signing_input}.{}", BASE64URL_NOPAD.encode(signature))
+}
+
+/// Verifies a compact JWT against the `did:key` in its `kid` and checks the
+/// `exp` and `nbf` claims, if present, against `now` (seconds since the unix
+/// epoch). Callers still have to check that `kid` is a key they trust.
+pub fn verify(token: &str, now: u64) -> Result<Verified> {
+    let mut parts = token.split('.');
+
+    let (Some(header), Some(claims), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        bail!("malformed jwt");
+    };
+
+    let signing_input = &token[..header.len() + 1 + claims.len()];
+
+    let header: Header =
+        serde_json::from_slice(&decode(header)?).context("malformed jwt header")?;
+    let claims: Value = serde_json::from_slice(&decode(claims)?).context("malformed jwt claims")?;
+    let signature = decode(signature)?;
+
+    let (did_key, fragment) = header.kid.split_once('#').context("kid is not a did:key")?;
+
+    if verification_method(did_key)? != header.kid {
+        bail!("kid fragment {fragment} does not match its did:key");
+    }
+
+    let (algorithm, _) = didkey::decode(did_key)?;
+
+    if header.alg != algorithm.jws_alg() {
+        bail!(
+            "alg {} does not match the {} kid",
+            header.alg,
+            algorithm.as_str()
+        );
+    }
+
+    didkey::verify(did_key, signing_input.as_bytes(), &signature)?;
+
+    let now = now as f64;
+
+    if numeric_date(&claims, "exp")?.is_some_and(|exp| now >= exp) {
+        bail!("jwt is expired");
+    }
+
+    if numeric_date(&claims, "nbf")?.is_some_and(|nbf| now < nbf) {
+        bail!("jwt is not yet valid");
+    }
+
+    Ok(Verified { header, claims })
+}
+
+/// A NumericDate claim, which may have a fraction; any other value is
+/// rejected rather than ignored, so that it can't skip the check.
+fn numeric_date(claims: &Value, name: &str) -> Result<Option<f64>> {
+    let Some(value) = claims.get(name) else {
+        return Ok(None);
+    };
+
+    match value.as_f64() {
+        Some(date) if date.is_finite() && date >= 0.0 => Ok(Some(date)),
+        _ => bail!("jwt {name} is not a NumericDate"),
+    }
+}
+
+fn decode(part: &str) -> Result<Vec<u8>> {
+    BASE64URL_NOPAD
+        .decode(part.as_bytes())
+        .context("malformed jwt base64url")
+}
@@ -0,0 +1,440 @@
+use std::fmt::{self, Debug};
+use std::str::FromStr;
+
+use anyhow::{Context, Error, Result, anyhow, bail};
+use k256::{ecdsa::signature::Signer, elliptic_curve::sec1::ToEncodedPoint};
+use p256::{
+    SecretKey,
+    pkcs8::{EncodePrivateKey, SecretDocument},
+};
+use ring::{
+    hkdf::{HKDF_SHA256, KeyType, Prk, Salt},
+    rand::SystemRandom,
+    signature::{self, EcdsaKeyPair, Ed25519KeyPair, KeyPair},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::Secret;
+
+const DOMAIN: &[u8] = b"sebastianvogelsang.com-mpds-demo-v1";
+const DOMAIN_V2: &[u8] = b"sebastianvogelsang.com-mpds-demo-v2";
+const P256_KEYLEN: usize = 32;
+const SECP256K1_KEYLEN: usize = 32;
+const ED25519_SEEDLEN: usize = 32;
+
+pub struct Keygen {
+    salt: Salt,
+    version: Version,
+}
+
+/// The derivation scheme, which has to be recorded alongside each credential
+/// so that its keys can be derived again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Version {
+    /// Plain concatenation of the HKDF info fields; kept for existing users.
+    V1,
+    /// Versioned HKDF info with length-prefixed fields, so that distinct
+    /// (did, key-type) pairs can never produce the same info.
+    V2,
+}
+
+impl Version {
+    pub const LATEST: Self = Self::V2;
+
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for Version {
+    type Error = Error;
+
+    fn try_from(version: u8) -> Result<Self> {
+        match version {
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            _ => bail!("unsupported derivation version {version}"),
+        }
+    }
+}
+
+/// The signature algorithm (and curve) of a derived key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    P256,
+    Secp256k1,
+    Ed25519,
+}
+
+/// What a derived key is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Purpose {
+    Signing,
+    Rotation,
+}
+
+/// Signing and PLC rotation keys derived from the same PRF.
+#[derive(Debug)]
+pub struct Keys {
+    pub signing: Keypair,
+    pub rotation: Keypair,
+}
+
+impl Algorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::P256 => "p256",
+            Self::Secp256k1 => "secp256k1",
+            Self::Ed25519 => "ed25519",
+        }
+    }
+
+    /// HKDF `key-type` info label, distinct per algorithm and purpose so that
+    /// each key gets independent key material. P-256 signing keeps the
+    /// original label so that existing users keep their keys.
+    fn label(&self, purpose: Purpose) -> &'static [u8] {
+        match (purpose, self) {
+            (Purpose::Signing, Self::P256) => b"signing",
+            (Purpose::Signing, Self::Secp256k1) => b"signing-secp256k1",
+            (Purpose::Signing, Self::Ed25519) => b"signing-ed25519",
+            (Purpose::Rotation, Self::P256) => b"rotation-p256",
+            (Purpose::Rotation, Self::Secp256k1) => b"rotation-secp256k1",
+            (Purpose::Rotation, Self::Ed25519) => b"rotation-ed25519",
+        }
+    }
+
+    /// Converts a signature as returned by [`Keypair::sign`] to ASN.1 DER, for
+    /// consumers such as WebCrypto or OpenSSL. Ed25519 has no DER form.
+    pub fn der_signature(&self, signature: &[u8]) -> Result<Option<Vec<u8>>> {
+        let der = match self {
+            Self::P256 => p256::ecdsa::Signature::from_slice(signature)
+                .context("malformed p256 signature")?
+                .to_der()
+                .as_bytes()
+                .to_vec(),
+            Self::Secp256k1 => k256::ecdsa::Signature::from_slice(signature)
+                .context("malformed secp256k1 signature")?
+                .to_der()
+                .as_bytes()
+                .to_vec(),
+            Self::Ed25519 => return Ok(None),
+        };
+
+        Ok(Some(der))
+    }
+
+    /// Normalizes an ECDSA `r || s` signature from another signer (such as
+    /// WebCrypto) to a low S value, like [`Keypair::sign`] does.
+    pub fn normalize_signature(&self, signature: &[u8]) -> Result<Vec<u8>> {
+        let signature = match self {
+            Self::P256 => {
+                let signature = p256::ecdsa::Signature::from_slice(signature)
+                    .context("malformed p256 signature")?;
+
+                signature.normalize_s().unwrap_or(signature).to_vec()
+            }
+            Self::Secp256k1 => {
+                let signature = k256::ecdsa::Signature::from_slice(signature)
+                    .context("malformed secp256k1 signature")?;
+
+                signature.normalize_s().unwrap_or(signature).to_vec()
+            }
+            Self::Ed25519 => signature.to_vec(),
+        };
+
+        Ok(signature)
+    }
+
+    fn key_len(&self) -> usize {
+        match self {
+            Self::P256 => P256_KEYLEN,
+            Self::Secp256k1 => SECP256K1_KEYLEN,
+            Self::Ed25519 => ED25519_SEEDLEN,
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "p256" => Ok(Self::P256),
+            "secp256k1" => Ok(Self::Secp256k1),
+            "ed25519" => Ok(Self::Ed25519),
+            _ => bail!("unsupported key type {s}"),
+        }
+    }
+}
+
+impl KeyType for Algorithm {
+    fn len(&self) -> usize {
+        self.key_len()
+    }
+}
+
+/// A derived keypair. The HKDF output each key is built from is held in a
+/// [`Secret`] and secp256k1 keys zeroize themselves on drop, but the HKDF
+/// pseudorandom key is a `ring` type that is not zeroized, and neither are the
+/// copies of P-256 and Ed25519 private keys that `ring` keeps.
+pub enum Keypair {
+    P256(Box<EcdsaKeyPair>),
+    Secp256k1(k256::SecretKey),
+    Ed25519(Box<Ed25519KeyPair>),
+}
+
+/// Only shows the algorithm and public key, never the private key.
+impl Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair")
+            .field("algorithm", &self.algorithm())
+            .field("public_key", &self.public_key())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Keypair {
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            Self::P256(_) => Algorithm::P256,
+            Self::Secp256k1(_) => Algorithm::Secp256k1,
+            Self::Ed25519(_) => Algorithm::Ed25519,
+        }
+    }
+
+    /// Uncompressed SEC1 encoding of ECDSA public keys, raw 32 bytes for Ed25519.
+    pub fn public_key(&self) -> Vec<u8> {
+        match self {
+            Self::P256(keypair) => keypair.public_key().as_ref().to_vec(),
+            Self::Secp256k1(key) => key.public_key().to_encoded_point(false).as_bytes().to_vec(),
+            Self::Ed25519(keypair) => keypair.public_key().as_ref().to_vec(),
+        }
+    }
+
+    /// Signs the message (hashed with SHA-256 for ECDSA keys), returning the
+    /// fixed size `r || s` encoding with a low S value, or the raw Ed25519
+    /// signature.
+    pub fn sign(&self, msg: &[u8]) -> Result<Vec<u8>> {
+        let signature = match self {
+            Self::P256(keypair) => {
+                let signature = keypair
+                    .sign(&SystemRandom::new(), msg)
+                    .map_err(|e| anyhow!("failed to sign message: {}", e))?;
+
+                let signature = p256::ecdsa::Signature::from_slice(signature.as_ref())
+                    .context("malformed p256 signature")?;
+
+                signature.normalize_s().unwrap_or(signature).to_vec()
+            }
+            Self::Secp256k1(key) => {
+                let signature: k256::ecdsa::Signature = k256::ecdsa::SigningKey::from(key)
+                    .try_sign(msg)
+                    .context("failed to sign message")?;
+
+                signature.normalize_s().unwrap_or(signature).to_vec()
+            }
+            Self::Ed25519(keypair) => keypair.sign(msg).as_ref().to_vec(),
+        };
+
+        Ok(signature)
+    }
+}
+
+impl Keygen {
+    pub fn new(version: Version) -> Self {
+        let domain = match version {
+            Version::V1 => DOMAIN,
+            Version::V2 => DOMAIN_V2,
+        };
+
+        let salt = Salt::new(HKDF_SHA256, domain);
+        Self { salt, version }
+    }
+
+    /// Derives the signing key.
+    pub fn generate(&self, prf: &[u8], did: &[u8], algorithm: Algorithm) -> Result<Keypair> {
+        let prk = self.salt.extract(prf);
+        self.derive(&prk, did, algorithm, Purpose::Signing)
+    }
+
+    /// Derives both the signing and the rotation key, each with its own
+    /// `key-type` label.
+    pub fn generate_keys(&self, prf: &[u8], did: &[u8], algorithm: Algorithm) -> Result<Keys> {
+        let prk = self.salt.extract(prf);
+
+        Ok(Keys {
+            signing: self.derive(&prk, did, algorithm, Purpose::Signing)?,
+            rotation: self.derive(&prk, did, algorithm, Purpose::Rotation)?,
+        })
+    }
+
+    /// Derives the rotation key only.
+    pub fn generate_rotation(
+        &self,
+        prf: &[u8],
+        did: &[u8],
+        algorithm: Algorithm,
+    ) -> Result<Keypair> {
+        let prk = self.salt.extract(prf);
+        self.derive(&prk, did, algorithm, Purpose::Rotation)
+    }
+
+    /// Derives the P-256 signing key as PKCS#8 DER, to import it into a key
+    /// store such as WebCrypto, along with its uncompressed public key. No
+    /// `ring` keypair is built, the document is zeroized on drop.
+    pub fn generate_p256_pkcs8(&self, prf: &[u8], did: &[u8]) -> Result<(SecretDocument, Vec<u8>)> {
+        let prk = self.salt.extract(prf);
+
+        for n in 0..8 {
+            let key = self.expand(&prk, n, did, Algorithm::P256, Purpose::Signing)?;
+
+            if let Some(pkcs8) = self.p256_pkcs8(&key)? {
+                let public_key = SecretKey::from_slice(&key)
+                    .context("malformed key")?
+                    .public_key()
+                    .to_encoded_point(false)
+                    .as_bytes()
+                    .to_vec();
+
+                return Ok((pkcs8, public_key));
+            }
+        }
+
+        bail!("cannot generate valid key, giving up");
+    }
+
+    fn derive(
+        &self,
+        prk: &Prk,
+        did: &[u8],
+        algorithm: Algorithm,
+        purpose: Purpose,
+    ) -> Result<Keypair> {
+        for n in 0..8 {
+            let key = self.expand(prk, n, did, algorithm, purpose)?;
+
+            let keypair = match algorithm {
+                Algorithm::P256 => self.p256(&key)?.map(|k| Keypair::P256(Box::new(k))),
+                Algorithm::Secp256k1 => self.secp256k1(&key).map(Keypair::Secp256k1),
+                Algorithm::Ed25519 => Some(Keypair::Ed25519(Box::new(self.ed25519(&key)?))),
+            };
+
+            if let Some(keypair) = keypair {
+                return Ok(keypair);
+            }
+        }
+
+        bail!("cannot generate valid key, giving up");
+    }
+
+    fn expand(
+        &self,
+        prk: &Prk,
+        n: u8,
+        did: &[u8],
+        algorithm: Algorithm,
+        purpose: Purpose,
+    ) -> Result<Secret> {
+        let mut key = Secret::new(vec![0u8; algorithm.key_len()]);
+
+        let round = [n];
+        let fields = [
+            (b"round".as_ref(), round.as_ref()),
+            (b"did".as_ref(), did),
+            (b"key-type".as_ref(), algorithm.label(purpose)),
+        ];
+
+        let info = match self.version {
+            Version::V1 => fields
+                .iter()
+                .flat_map(|(name, value)| [name.to_vec(), value.to_vec()])
+                .collect(),
+            Version::V2 => {
+                let mut info = vec![self.version.as_u8()];
+
+                for (name, value) in fields {
+                    for field in [name, value] {
+                        let len = u32::try_from(field.len()).context("info field too long")?;
+                        info.extend_from_slice(&len.to_be_bytes());
+                        info.extend_from_slice(field);
+                    }
+                }
+
+                vec![info]
+            }
+        };
+
+        let info = info.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        let okm = prk
+            .expand(&info, algorithm)
+            .map_err(|e| anyhow!("failed to expand key: {}", e))?;
+
+        okm.fill(&mut key)
+            .map_err(|e| anyhow!("failed to fill key buffer: {}", e))?;
+
+        Ok(key)
+    }
+
+    fn p256(&self, key: &[u8]) -> Result<Option<EcdsaKeyPair>> {
+        let Some(key) = self.p256_pkcs8(key)? else {
+            return Ok(None);
+        };
+
+        let rng = SystemRandom::new();
+
+        let keypair = EcdsaKeyPair::from_pkcs8(
+            &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+            key.as_bytes(),
+            &rng,
+        )
+        .ok();
+
+        Ok(keypair)
+    }
+
+    fn p256_pkcs8(&self, key: &[u8]) -> Result<Option<SecretDocument>> {
+        // Rejects zero and scalars outside of the curve order, so that the
+        // next round is tried
+        let Ok(key) = SecretKey::from_slice(key) else {
+            return Ok(None);
+        };
+        let key = key
+            .to_pkcs8_der()
+            .context("failed to convert key to PKCS8 DER")?;
+
+        Ok(Some(key))
+    }
+
+    fn secp256k1(&self, key: &[u8]) -> Option<k256::SecretKey> {
+        // Rejects zero and scalars outside of the curve order
+        k256::SecretKey::from_slice(key).ok()
+    }
+
+    /// Every 32 byte seed is a valid Ed25519 key, so this always succeeds in
+    /// the first round.
+    fn ed25519(&self, seed: &[u8]) -> Result<Ed25519KeyPair> {
+        Ed25519KeyPair::from_seed_unchecked(seed)
+            .map_err(|e| anyhow!("failed to create ed25519 keypair: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn p256_rejects_out_of_range_scalars() {
+        let keygen = Keygen::new(Version::V2);
+
+        assert!(keygen.p256_pkcs8(&[0; P256_KEYLEN]).unwrap().is_none());
+        assert!(keygen.p256_pkcs8(&[0xff; P256_KEYLEN]).unwrap().is_none());
+        assert!(keygen.p256(&[0xff; P256_KEYLEN]).unwrap().is_none());
+        assert!(keygen.p256_pkcs8(&[1; P256_KEYLEN]).unwrap().is_some());
+    }
+}
@@ -0,0 +1,14 @@
+//! Deterministic key derivation from the WebAuthn PRF extension output.
+//!
+//! Builds both for the browser (`wasm32-unknown-unknown`) and natively, so the
+//! same derivation can be used from the WASM client, the server and tools.
+
+pub mod didkey;
+pub mod jws;
+mod keys;
+pub mod plc;
+pub mod proof;
+mod secret;
+
+pub use keys::{Algorithm, Keygen, Keypair, Keys, Purpose, Version};
+pub use secret::Secret;
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, bail};
+use data_encoding::{BASE32_NOPAD, BASE64URL_NOPAD};
+use ring::digest::{SHA256, digest};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::didkey;
+use crate::{Algorithm, Keypair};
+
+const DID_PLC_PREFIX: &str = "did:plc:";
+const DID_PLC_LEN: usize = 24;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Operation {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub rotation_keys: Vec<String>,
+    pub verification_methods: BTreeMap<String, String>,
+    pub also_known_as: Vec<String>,
+    pub services: BTreeMap<String, Service>,
+    pub prev: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Service {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub endpoint: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SignedOperation {
+    #[serde(flatten)]
+    pub operation: Operation,
+    pub sig: String,
+}
+
+impl Operation {
+    /// Builds a genesis operation with the rotation key as the only rotation
+    /// key and the signing key as the atproto verification method, both given
+    /// as `did:key`s.
+    pub fn genesis(rotation_key: &str, signing_key: &str, handle: &str, pds: &str) -> Result<Self> {
+        for key in [rotation_key, signing_key] {
+            let (algorithm, _) = didkey::decode(key)?;
+
+            if algorithm == Algorithm::Ed25519 {
+                bail!("did:plc requires p256 or secp256k1 keys");
+            }
+        }
+
+        let service = Service {
+            kind: "AtprotoPersonalDataServer".to_owned(),
+            endpoint: pds.to_owned(),
+        };
+
+        Ok(Self {
+            kind: "plc_operation".to_owned(),
+            rotation_keys: vec![rotation_key.to_owned()],
+            verification_methods: BTreeMap::from([("atproto".to_owned(), signing_key.to_owned())]),
+            also_known_as: vec![format!("at://{handle}")],
+            services: BTreeMap::from([("atproto_pds".to_owned(), service)]),
+            prev: None,
+        })
+    }
+
+    /// Signs the DAG-CBOR encoding of the operation with a low-S ECDSA
+    /// signature.
+    pub fn sign(self, rotation: &Keypair) -> Result<SignedOperation> {
+        let sig = rotation.sign(&self.to_dag_cbor()?)?;
+
+        Ok(SignedOperation {
+            operation: self,
+            sig: BASE64URL_NOPAD.encode(&sig),
+        })
+    }
+
+    /// The bytes covered by the signature.
+    pub fn to_dag_cbor(&self) -> Result<Vec<u8>> {
+        dag_cbor(&serde_json::to_value(self)?)
+    }
+}
+
+impl SignedOperation {
+    /// The DID is the truncated base32 SHA-256 of the signed genesis operation.
+    pub fn did(&self) -> Result<String> {
+        let hash = BASE32_NOPAD
+            .encode(digest(&SHA256, &self.to_dag_cbor()?).as_ref())
+            .to_lowercase();
+
+        Ok(format!("{DID_PLC_PREFIX}{}", &hash[..DID_PLC_LEN]))
+    }
+
+    /// The bytes hashed into the DID.
+    pub fn to_dag_cbor(&self) -> Result<Vec<u8>> {
+        dag_cbor(&serde_json::to_value(self)?)
+    }
+}
+
+/// A PLC directory such as plc.directory or a local did-method-plc instance.
+pub struct PlcDirectory {
+    endpoint: String,
+}
+
+impl PlcDirectory {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    /// Submits the signed genesis operation, returning the created DID.
+    ///
+    /// `post` sends the JSON body to the URL and returns the response status
+    /// and body, so that the browser and native clients bring their own HTTP
+    /// client.
+    pub async fn create<F, Fut>(&self, op: &SignedOperation, post: F) -> Result<String>
+    where
+        F: FnOnce(String, Value) -> Fut,
+        Fut: Future<Output = Result<(u16, String)>>,
+    {
+        let did = op.did()?;
+
+        let (status, body) = post(
+            format!("{}/{}", self.endpoint, did),
+            serde_json::to_value(op)?,
+        )
+        .await?;
+
+        if !(200..300).contains(&status) {
+            bail!("plc directory rejected operation ({status}): {body}");
+        }
+
+        Ok(did)
+    }
+}
+
+/// Encodes the JSON data model subset used by PLC operations as DAG-CBOR,
+/// i.e. with minimal length headers and map keys sorted by length first.
+fn dag_cbor(value: &Value) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    encode_value(value, &mut out)?;
+    Ok(out)
+}
+
+fn encode_value(value: &Value, out: &mut Vec<u8>) -> Result<()> {
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(false) => out.push(0xf4),
+        Value::Bool(true) => out.push(0xf5),
+        Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(n), _) => encode_head(0, n, out),
+            (None, Some(n)) => encode_head(1, n.unsigned_abs() - 1, out),
+            _ => bail!("floats are not supported in plc operations"),
+        },
+        Value::String(s) => {
+            encode_head(3, s.len() as u64, out);
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Array(values) => {
+            encode_head(4, values.len() as u64, out);
+
+            for value in values {
+                encode_value(value, out)?;
+            }
+        }
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+
+            encode_head(5, map.len() as u64, out);
+
+            for key in keys {
+                encode_head(3, key.len() as u64, out);
+                out.extend_from_slice(key.as_bytes());
+                encode_value(&map[key], out)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn encode_head(major: u8, n: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+
+    match n {
+        0..=23 => out.push(major | n as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, n as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(n as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//! Proof of possession for derived keys, binding them to a DID during a
+//! WebAuthn ceremony by signing over its challenge.
+
+use anyhow::{Context, Result};
+
+use crate::{Keypair, didkey};
+
+const DOMAIN: &[u8] = b"sebastianvogelsang.com-mpds-demo-proof-v1";
+
+/// The signed message: domain, DID and challenge, each with a 4 byte
+/// big-endian length prefix.
+pub fn message(did: &str, challenge: &[u8]) -> Result<Vec<u8>> {
+    let mut msg = Vec::new();
+
+    for field in [DOMAIN, did.as_bytes(), challenge] {
+        let len = u32::try_from(field.len()).context("proof field too long")?;
+        msg.extend_from_slice(&len.to_be_bytes());
+        msg.extend_from_slice(field);
+    }
+
+    Ok(msg)
+}
+
+pub fn sign(keypair: &Keypair, did: &str, challenge: &[u8]) -> Result<Vec<u8>> {
+    keypair.sign(&message(did, challenge)?)
+}
+
+/// Verifies that the holder of the `did:key` signed the challenge for `did`.
+pub fn verify(did_key: &str, did: &str, challenge: &[u8], signature: &[u8]) -> Result<()> {
+    didkey::verify(did_key, &message(did, challenge)?, signature)
+}
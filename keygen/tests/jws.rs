@@ -0,0 +1,68 @@
+use keygen::{Algorithm, Keygen, Version, didkey, jws};
+use serde_json::json;
+
+const PRF: [u8; 32] = [7; 32];
+const DID: &str = "did:plc:ewvi7nxzyoun6zhxrhs64oiz";
+const NOW: u64 = 1_700_000_000;
+
+#[test]
+fn signs_and_verifies_jwts() {
+    for algorithm in [Algorithm::P256, Algorithm::Secp256k1, Algorithm::Ed25519] {
+        let keypair = Keygen::new(Version::V2)
+            .generate(&PRF, DID.as_bytes(), algorithm)
+            .unwrap();
+
+        let claims = json!({ "iss": DID, "exp": NOW + 60 });
+        let token = jws::sign(&keypair, &claims).unwrap();
+
+        let verified = jws::verify(&token, NOW).unwrap();
+        let did_key = didkey::encode(algorithm, &keypair.public_key()).unwrap();
+
+        assert_eq!(verified.claims, claims);
+        assert_eq!(verified.header.alg, algorithm.jws_alg());
+        assert_eq!(
+            verified.header.kid,
+            jws::verification_method(&did_key).unwrap()
+        );
+
+        assert!(jws::verify(&token, NOW + 60).is_err());
+    }
+}
+
+#[test]
+fn rejects_tampered_jwts() {
+    let keypair = Keygen::new(Version::V2)
+        .generate(&PRF, DID.as_bytes(), Algorithm::P256)
+        .unwrap();
+
+    let token = jws::sign(&keypair, &json!({ "sub": "alice" })).unwrap();
+    let forged = jws::sign(&keypair, &json!({ "sub": "mallory" })).unwrap();
+
+    let (header, rest) = token.split_once('.').unwrap();
+    let (_, signature) = rest.split_once('.').unwrap();
+    let (_, forged_claims) = forged.split_once('.').unwrap();
+    let (forged_claims, _) = forged_claims.split_once('.').unwrap();
+
+    let tampered = format!("{header}.{forged_claims}.{signature}");
+
+    assert!(jws::verify(&tampered, NOW).is_err());
+}
+
+#[test]
+fn checks_numeric_dates() {
+    let keypair = Keygen::new(Version::V2)
+        .generate(&PRF, DID.as_bytes(), Algorithm::P256)
+        .unwrap();
+
+    let verify = |claims| jws::verify(&jws::sign(&keypair, &claims).unwrap(), NOW);
+
+    // NumericDates may have fractions
+    assert!(verify(json!({ "exp": 1.7e9 - 0.5 })).is_err());
+    assert!(verify(json!({ "exp": NOW as f64 + 0.5 })).is_ok());
+    assert!(verify(json!({ "nbf": NOW as f64 + 0.5 })).is_err());
+
+    // Anything else must not skip the check
+    assert!(verify(json!({ "exp": -1 })).is_err());
+    assert!(verify(json!({ "exp": "2100-01-01" })).is_err());
+    assert!(verify(json!({ "nbf": null })).is_err());
+}
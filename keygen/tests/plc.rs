@@ -0,0 +1,194 @@
+//! Known-answer tests for PLC operations. The encoding is cross-checked with
+//! serde_cbor_2, whose canonical map key order matches DAG-CBOR's, and the DID
+//! was computed independently from the signed bytes.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use data_encoding::{BASE64URL_NOPAD, HEXLOWER};
+use keygen::plc::{Operation, PlcDirectory, Service, SignedOperation};
+use keygen::{Algorithm, Keygen, Keypair, Version, didkey};
+use serde_cbor_2::Value as Cbor;
+use serde_json::{Value, json};
+
+const PRF: [u8; 32] = [7; 32];
+const DID: &str = "did:plc:ewvi7nxzyoun6zhxrhs64oiz";
+
+const ROTATION_KEY: &str = "did:key:zQ3shdBpTMh7Pc3ywHShVCfzyjVnxsjhETc8UHHfVU4TFpcEa";
+const SIGNING_KEY: &str = "did:key:zDnaeUMhFJqmUUmDKFpsqM4DFHidU3Ehg6rUU5nC8dcWDgTR4";
+const SIG: &str =
+    "1OdsNzBZnfRk-Pta7FJe9oaJL22VBXQAkAs1wrXvCT8OKlYpVRHAgU06zBjDeQi0PCLPbGgVEGAoYDndZQqU1A";
+
+const OPERATION_CBOR: &str = concat!(
+    "a66470726576f664747970656d706c635f6f7065726174696f6e687365727669",
+    "636573a16b617470726f746f5f706473a264747970657819417470726f746f50",
+    "6572736f6e616c4461746153657276657268656e64706f696e74776874747073",
+    "3a2f2f7064732e6578616d706c652e636f6d6b616c736f4b6e6f776e41738176",
+    "61743a2f2f616c6963652e6578616d706c652e636f6d6c726f746174696f6e4b",
+    "6579738178396469643a6b65793a7a51337368644270544d6837506333797748",
+    "53685643667a796a566e78736a68455463385548486656553454467063456173",
+    "766572696669636174696f6e4d6574686f6473a167617470726f746f78396469",
+    "643a6b65793a7a446e6165554d68464a716d55556d444b467073714d34444648",
+    "6964553345686736725555356e43386463574467545234",
+);
+const SIGNED_DID: &str = "did:plc:pn2zqx5v25pe23ccekbmv4tf";
+
+fn operation() -> Operation {
+    Operation {
+        kind: "plc_operation".to_owned(),
+        rotation_keys: vec![ROTATION_KEY.to_owned()],
+        verification_methods: BTreeMap::from([("atproto".to_owned(), SIGNING_KEY.to_owned())]),
+        also_known_as: vec!["at://alice.example.com".to_owned()],
+        services: BTreeMap::from([(
+            "atproto_pds".to_owned(),
+            Service {
+                kind: "AtprotoPersonalDataServer".to_owned(),
+                endpoint: "https://pds.example.com".to_owned(),
+            },
+        )]),
+        prev: None,
+    }
+}
+
+/// Encodes JSON through serde_cbor_2's canonically ordered maps.
+fn canonical(value: &Value) -> Vec<u8> {
+    fn convert(value: &Value) -> Cbor {
+        match value {
+            Value::Null => Cbor::Null,
+            Value::Bool(b) => Cbor::Bool(*b),
+            Value::Number(n) => Cbor::Integer(n.as_i64().unwrap().into()),
+            Value::String(s) => Cbor::Text(s.clone()),
+            Value::Array(values) => Cbor::Array(values.iter().map(convert).collect()),
+            Value::Object(map) => Cbor::Map(
+                map.iter()
+                    .map(|(key, value)| (Cbor::Text(key.clone()), convert(value)))
+                    .collect(),
+            ),
+        }
+    }
+
+    serde_cbor_2::to_vec(&convert(value)).unwrap()
+}
+
+#[test]
+fn encodes_operations() {
+    let operation = operation();
+    let bytes = operation.to_dag_cbor().unwrap();
+
+    assert_eq!(HEXLOWER.encode(&bytes), OPERATION_CBOR);
+    assert_eq!(bytes, canonical(&serde_json::to_value(&operation).unwrap()));
+
+    let signed = SignedOperation {
+        operation,
+        sig: SIG.to_owned(),
+    };
+
+    assert_eq!(
+        signed.to_dag_cbor().unwrap(),
+        canonical(&serde_json::to_value(&signed).unwrap())
+    );
+    assert_eq!(signed.did().unwrap(), SIGNED_DID);
+}
+
+#[test]
+fn verifies_signatures() {
+    let signature = BASE64URL_NOPAD.decode(SIG.as_bytes()).unwrap();
+    let bytes = operation().to_dag_cbor().unwrap();
+
+    didkey::verify(ROTATION_KEY, &bytes, &signature).unwrap();
+
+    let mut tampered = operation();
+    tampered.also_known_as = vec!["at://mallory.example.com".to_owned()];
+    assert!(didkey::verify(ROTATION_KEY, &tampered.to_dag_cbor().unwrap(), &signature).is_err());
+}
+
+#[test]
+fn signs_genesis_operations() {
+    for algorithm in [Algorithm::P256, Algorithm::Secp256k1] {
+        let keys = Keygen::new(Version::V2)
+            .generate_keys(&PRF, DID.as_bytes(), algorithm)
+            .unwrap();
+        let rotation_key = did_key(&keys.rotation);
+
+        let operation = Operation::genesis(
+            &rotation_key,
+            &did_key(&keys.signing),
+            "alice.example.com",
+            "https://pds.example.com",
+        )
+        .unwrap();
+        let bytes = operation.to_dag_cbor().unwrap();
+
+        let signed = operation.sign(&keys.rotation).unwrap();
+        let signature = BASE64URL_NOPAD.decode(signed.sig.as_bytes()).unwrap();
+        didkey::verify(&rotation_key, &bytes, &signature).unwrap();
+
+        let did = signed.did().unwrap();
+        assert!(did.starts_with("did:plc:"));
+        assert_eq!(did.len(), "did:plc:".len() + 24);
+    }
+
+    let keys = Keygen::new(Version::V2)
+        .generate_keys(&PRF, DID.as_bytes(), Algorithm::Ed25519)
+        .unwrap();
+    let operation = Operation::genesis(
+        &did_key(&keys.rotation),
+        &did_key(&keys.signing),
+        "alice.example.com",
+        "https://pds.example.com",
+    );
+    assert!(operation.is_err());
+}
+
+#[tokio::test]
+async fn submits_to_directories() {
+    let signed = SignedOperation {
+        operation: operation(),
+        sig: SIG.to_owned(),
+    };
+    let directory = PlcDirectory::new("http://localhost:2582/");
+
+    // A mock directory recording the requests it accepts
+    let requests = RefCell::new(Vec::new());
+    let did = directory
+        .create(&signed, |url, body| {
+            requests.borrow_mut().push((url, body));
+            async { Ok((200, String::new())) }
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(did, SIGNED_DID);
+    assert_eq!(
+        requests.into_inner(),
+        [(
+            format!("http://localhost:2582/{SIGNED_DID}"),
+            json!({
+                "type": "plc_operation",
+                "rotationKeys": [ROTATION_KEY],
+                "verificationMethods": { "atproto": SIGNING_KEY },
+                "alsoKnownAs": ["at://alice.example.com"],
+                "services": {
+                    "atproto_pds": {
+                        "type": "AtprotoPersonalDataServer",
+                        "endpoint": "https://pds.example.com",
+                    },
+                },
+                "prev": null,
+                "sig": SIG,
+            }),
+        )]
+    );
+
+    let err = directory
+        .create(&signed, |_, _| async {
+            Ok((400, "Invalid signature".to_owned()))
+        })
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("(400): Invalid signature"));
+}
+
+fn did_key(keypair: &Keypair) -> String {
+    didkey::encode(keypair.algorithm(), &keypair.public_key()).unwrap()
+}
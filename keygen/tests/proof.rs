@@ -0,0 +1,22 @@
+use keygen::{Algorithm, Keygen, Version, didkey, proof};
+
+const PRF: [u8; 32] = [7; 32];
+const DID: &str = "did:plc:ewvi7nxzyoun6zhxrhs64oiz";
+const CHALLENGE: &[u8] = b"challenge";
+
+#[test]
+fn verifies_proof_of_possession() {
+    for algorithm in [Algorithm::P256, Algorithm::Secp256k1, Algorithm::Ed25519] {
+        let keypair = Keygen::new(Version::V2)
+            .generate(&PRF, DID.as_bytes(), algorithm)
+            .unwrap();
+
+        let did_key = didkey::encode(algorithm, &keypair.public_key()).unwrap();
+        let signature = proof::sign(&keypair, DID, CHALLENGE).unwrap();
+
+        proof::verify(&did_key, DID, CHALLENGE, &signature).unwrap();
+
+        assert!(proof::verify(&did_key, DID, b"other challenge", &signature).is_err());
+        assert!(proof::verify(&did_key, "did:web:example.com", CHALLENGE, &signature).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Known-answer tests for the key derivation. Changing any of these vectors
+//! changes the keys of existing users.
+
+use keygen::{Algorithm, Keygen, Version, didkey};
+use p256::{elliptic_curve::sec1::ToEncodedPoint, pkcs8::DecodePrivateKey};
+
+struct Vector {
+    version: Version,
+    prf: &'static str,
+    did: &'static str,
+    algorithm: Algorithm,
+    signing: &'static str,
+    rotation: &'static str,
+    did_key: &'static str,
+}
+
+const PRF_SEQUENTIAL: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const PRF_ONES: &str = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+const VECTORS: &[Vector] = &[
+    Vector {
+        version: Version::V1,
+        prf: PRF_SEQUENTIAL,
+        did: "did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+        algorithm: Algorithm::P256,
+        signing: "0417fb1578c5a5d77af7005c3ce72d90be97dde5fae1a1ca09a0c8553bda581554427fcaf210efe03857d214c55a83d85e69060cb727727d0217ffd791e5e9923c",
+        rotation: "0492c0486a9f079f004a1ec7d8c95719c642d095a6603a2f0a4a0881ad8e893641aa90e6366a679adb48c6e1aa78fe05fd291e190b664e1e52dfe6db64a55bd3ba",
+        did_key: "did:key:zDnaeS3aUyLgKkdfCtgfLuYLNShoXnuM477pvbC4MX1rr76m9",
+    },
+    Vector {
+        version: Version::V1,
+        prf: PRF_SEQUENTIAL,
+        did: "did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+        algorithm: Algorithm::Secp256k1,
+        signing: "0448f73f213def6e041502f04e2478133303cc69000f9c536173fbdb5eacc08273c9dbecc5e63f7d225666c8d767d079ac25aae043409e1758c56ffc662bf289c8",
+        rotation: "04d46ac0cac9d8882182cc5c753f401196216c0019b89623c8d831092a8adbec63cf2d2bfa32e1efa31e6402abea47826fc0f11fc049ecf9d1735ce4ca585e57a1",
+        did_key: "did:key:zQ3shSKcn2JYwuKAevqrsMZ8ATXSMLuZN7HshQreQUXxUjUEN",
+    },
+    Vector {
+        version: Version::V1,
+        prf: PRF_SEQUENTIAL,
+        did: "did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+        algorithm: Algorithm::Ed25519,
+        signing: "aa5d691f45bc405f22bc0463364b676a6fc641f528a8f7aebb68435b5cbf5c52",
+        rotation: "c0c06b9d585af3db4e1f5365c8704819770d92e03050522014ebf83e4836923b",
+        did_key: "did:key:z6MkqvHy32bbgwqvhQHnpK4P8fcTcUnA6F16AGo4d68G9My3",
+    },
+    Vector {
+        version: Version::V1,
+        prf: PRF_ONES,
+        did: "did:web:example.com",
+        algorithm: Algorithm::P256,
+        signing: "045be8a0f3e1cbc748e1c31d887e01720a9e75d57f3330a39db3c84a77a0591cb63e7d115c848671115330ffa165af1930f0bfef045b7610427afd662b63bef472",
+        rotation: "048a4cbde01ad0132713a2450efd8a67f2c06c177fbb83346a58f821da64b90e1b4000b0eb8f3723f252683773bbcd4e282e32d874c1dc3a60c8250886459f4cf2",
+        did_key: "did:key:zDnaeWcjtGNzuVkiurLc6t7q1HJoAYezrHyGdDgYoEzkyBNh3",
+    },
+    Vector {
+        version: Version::V1,
+        prf: PRF_ONES,
+        did: "did:web:example.com",
+        algorithm: Algorithm::Secp256k1,
+        signing: "046308573c57bc9370fbaf46cf11989924fc274b1eed6917f5afa88fd89b8868bedbebab08cd1a59e99f830d927f2fb2d8db58e992a99d960e0ce5f4289c6ffe3e",
+        rotation: "045dd49cf26736489a50e93a65af4a0797563b4d96f3744324757d9df417248d16dd93138ff50ed993266c5e0d7fc944146a62f52e0b8ed4a8a307929c922abb3f",
+        did_key: "did:key:zQ3shU5NVggoWsVeysWBwVfx6QfcoZXNV2bby11EBJAzxZiEy",
+    },
+    Vector {
+        version: Version::V1,
+        prf: PRF_ONES,
+        did: "did:web:example.com",
+        algorithm: Algorithm::Ed25519,
+        signing: "6c0ddb4ed35b3b39e2968dd9d07d0824da2289dc9a0579c8ad3a61c1677152aa",
+        rotation: "62a0aad9fb71597a3896c7f83386feec6f8e960101221faf675946f6faa7c1d6",
+        did_key: "did:key:z6Mkmj4LCFikpxsnnZuqrhJHykTuiX7FuGTgHZvQ7NXKzdaR",
+    },
+    Vector {
+        version: Version::V2,
+        prf: PRF_SEQUENTIAL,
+        did: "did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+        algorithm: Algorithm::P256,
+        signing: "0497881dccabd9c31395967c1cc1f9436227d32f3fcb95f7c41311c79f5107bf830b61fe4275bdc7ecfc806f1a06720878a65936d3ca407294ed86572050a09ac7",
+        rotation: "04ef1ac1976be65d74b53b821052cfed8771f2cd3361fd5883c0789926d4251eb78281d7ad74ed7375b6d8b5a40b450784d2dfd1306586c4d7a09b1e63a0119623",
+        did_key: "did:key:zDnaesroMuMqSUum2ryabR6iyU7qzKHv3BkXbS6TmjupZ3bcW",
+    },
+    Vector {
+        version: Version::V2,
+        prf: PRF_SEQUENTIAL,
+        did: "did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+        algorithm: Algorithm::Secp256k1,
+        signing: "04ea4564a8da8de6dc4a734ca280f0c9fa1251605abdd082faed49d0a8c7ed3f7930fdd3338d80d22ba83647b762845226d3409e2bf469a4118584c0c80d8cc3fa",
+        rotation: "04fc49eac0e32ef7aefa6aec093b8154a8ce26389a4c340099e612b427d9ca4f912b11232f322a4461215dc16d7f7c1eb64fb2313db4c3597201e3694ad1853e6f",
+        did_key: "did:key:zQ3shdBHXg1jp8sXpqtMSU81HfLZyiXE7Pjq1VBVii9UqVHx4",
+    },
+    Vector {
+        version: Version::V2,
+        prf: PRF_SEQUENTIAL,
+        did: "did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+        algorithm: Algorithm::Ed25519,
+        signing: "cb76c1cf485c23236c8beb44871943ca13e867322c3f010174979c50ff701048",
+        rotation: "375cb09053bd1fd42ed497acf85f56339ffebc5958365f4f6d66edc5ab1a58f5",
+        did_key: "did:key:z6Mkt9VqXXrba6Br7SnGHCw8NAwMFf327aPFRhjvf2yd624o",
+    },
+    Vector {
+        version: Version::V2,
+        prf: PRF_ONES,
+        did: "did:web:example.com",
+        algorithm: Algorithm::P256,
+        signing: "04a2452eb03764dcb2f9e2179b7c22bdae8fcb9ac6d3ca4f22e52d18a028c6e24018240f56208671b20c2ad2be59a3ab255129efd432c1541cee1063cf5ace10b0",
+        rotation: "04986d3dcd73f06823f207940a137a8b3d416f6f2747a4e18995e7838a284d94d4d0595e163a4fe5e09f67c2a992397067862f24583e6c1c50e50d146eb4f2c1cb",
+        did_key: "did:key:zDnaebMQH71GXtWDe8KJC4LDsmgxqXPbwVHRRZXaTSyFXbDKD",
+    },
+    Vector {
+        version: Version::V2,
+        prf: PRF_ONES,
+        did: "did:web:example.com",
+        algorithm: Algorithm::Secp256k1,
+        signing: "0465d2972a03ce8b7a65805dd12850005bd5507a00fb5c579df33dd46730b7cb48780b121919680ce4823abf435e25e781bba5b0492fcda2da4192f1cd8ee046dd",
+        rotation: "04eb795ab69c1291cb5b01c934b7e9c1eb9a71d6cc3095b6b0f55a27b376412ab95e849bac0b2688aa75be73d1d688544d63e348a458acb1ff078e3c7aa29a2193",
+        did_key: "did:key:zQ3shmVaYyRionv2boaSjgqnZA1SACEC1HyqHg6ZJeap34xbZ",
+    },
+    Vector {
+        version: Version::V2,
+        prf: PRF_ONES,
+        did: "did:web:example.com",
+        algorithm: Algorithm::Ed25519,
+        signing: "0b47b43fc2de76de921364ac9089f25b05b7bd4866632710f53080fdcd04a922",
+        rotation: "f8a24ae0fc781fb2dbac039c08cf539da39ca6d753169a6a9671849c2d58719c",
+        did_key: "did:key:z6MkfDHwoKxsUSXSoWnpBPXCxEY39gYeGEVvPBJngsff1bFF",
+    },
+];
+
+fn hex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn derives_known_keys() {
+    for vector in VECTORS {
+        let keys = Keygen::new(vector.version)
+            .generate_keys(&hex(vector.prf), vector.did.as_bytes(), vector.algorithm)
+            .unwrap();
+
+        assert_eq!(keys.signing.algorithm(), vector.algorithm);
+        assert_eq!(keys.signing.public_key(), hex(vector.signing));
+        assert_eq!(keys.rotation.public_key(), hex(vector.rotation));
+    }
+}
+
+#[test]
+fn generate_matches_signing_key() {
+    for vector in VECTORS {
+        let keypair = Keygen::new(vector.version)
+            .generate(&hex(vector.prf), vector.did.as_bytes(), vector.algorithm)
+            .unwrap();
+
+        assert_eq!(keypair.public_key(), hex(vector.signing));
+    }
+}
+
+#[test]
+fn generate_rotation_matches_rotation_key() {
+    for vector in VECTORS {
+        let keypair = Keygen::new(vector.version)
+            .generate_rotation(&hex(vector.prf), vector.did.as_bytes(), vector.algorithm)
+            .unwrap();
+
+        assert_eq!(keypair.public_key(), hex(vector.rotation));
+    }
+}
+
+#[test]
+fn pkcs8_matches_p256_signing_key() {
+    for vector in VECTORS.iter().filter(|v| v.algorithm == Algorithm::P256) {
+        let (pkcs8, public_key) = Keygen::new(vector.version)
+            .generate_p256_pkcs8(&hex(vector.prf), vector.did.as_bytes())
+            .unwrap();
+
+        let key = p256::SecretKey::from_pkcs8_der(pkcs8.as_bytes()).unwrap();
+        let decoded = key.public_key().to_encoded_point(false);
+
+        assert_eq!(decoded.as_bytes(), hex(vector.signing));
+        assert_eq!(public_key, hex(vector.signing));
+    }
+}
+
+#[test]
+fn encodes_known_did_keys() {
+    for vector in VECTORS {
+        let encoded = didkey::encode(vector.algorithm, &hex(vector.signing)).unwrap();
+        assert_eq!(encoded, vector.did_key);
+
+        let (algorithm, public_key) = didkey::decode(vector.did_key).unwrap();
+        assert_eq!(algorithm, vector.algorithm);
+        assert_eq!(
+            didkey::encode(algorithm, &public_key).unwrap(),
+            vector.did_key
+        );
+    }
+}
@@ -1,53 +1,60 @@
-use std::collections::{HashMap, hash_map::Entry};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use api::*;
-use axum::{Json, Router, extract::State, routing::post};
+use axum::{
+    Json, Router,
+    extract::{FromRequest, Path, State},
+    routing::{get, post},
+};
+use keygen::{Algorithm, didkey};
 use tower_http::{services::ServeDir, trace::TraceLayer};
 use tower_sessions::{
     Expiry, MemoryStore, SessionManagerLayer,
     cookie::{Key, time::Duration},
 };
-use tracing::{info, instrument};
-use url::Url;
+use tracing::{info, instrument, warn};
 use uuid::Uuid;
-use webauthn_rs::{Webauthn, WebauthnBuilder, prelude::*};
+use webauthn_rs::prelude::{CreationChallengeResponse, DiscoverableKey};
+use webauthn_rs::{Webauthn, WebauthnBuilder};
+use webauthn_rs_proto::ResidentKeyRequirement;
 
-use crate::app::session::{AppSessionState, Session};
-use crate::error::{Context, Error, Result};
+use crate::app::session::{AppSessionState, Authenticated, Ceremonies, Session};
+use crate::config::Config;
+use crate::error::{Context, Error, ErrorCodeExt, Result};
+use crate::store::{self, Credential, DidKeys, Storage, User, UserStore};
 
-pub(crate) struct App {
-    id: String,
-    origin: Url,
+pub struct App {
+    config: Config,
 }
 
 struct AppState {
-    database: Mutex<HashMap<Username, User>>,
+    users: Arc<dyn UserStore>,
     webauthn: Webauthn,
+    ceremonies: Ceremonies,
 }
 
-type Username = String;
-
-struct User {
-    id: Uuid,
-    passkey: Option<Passkey>,
-}
+/// A JSON request body, rejected with a `bad_request` error if malformed.
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(Error))]
+struct Body<T>(T);
 
 mod session {
 
     const SESSION_KEY: &str = "app-session";
 
+    use std::collections::HashMap;
     use std::fmt::Debug;
+    use std::sync::Mutex;
+    use std::time::Duration;
 
-    use axum::{
-        extract::FromRequestParts,
-        http::{StatusCode, request::Parts},
-    };
+    use api::ErrorCode;
+    use axum::{extract::FromRequestParts, http::request::Parts};
     use serde::{Deserialize, Serialize};
     use uuid::Uuid;
     use webauthn_rs::prelude::*;
 
-    use crate::error::{Context, Result};
+    use crate::error::{Context, Error, ErrorCodeExt, Result};
+    use crate::store;
 
     #[derive(Default, Debug, Deserialize, Serialize)]
     pub(crate) struct AppSession {
@@ -55,17 +62,140 @@ mod session {
         pub(crate) user_id: Option<Uuid>,
     }
 
-    #[derive(Debug, Deserialize, Serialize)]
+    #[derive(Default, Debug, Deserialize, Serialize)]
     pub(crate) enum AppSessionState {
+        #[default]
         Anonymous,
         Authenticated,
-        Authenticating(PasskeyAuthentication),
-        Registering(PasskeyRegistration),
+        Authenticating {
+            authentication: PasskeyAuthentication,
+            challenge: Base64UrlSafeData,
+            issued_at: u64,
+        },
+        /// A usernameless login, the user is only known once it finishes.
+        DiscoverableAuthenticating {
+            authentication: DiscoverableAuthentication,
+            challenge: Base64UrlSafeData,
+            issued_at: u64,
+        },
+        /// A finished usernameless login, waiting for the proof of the key the
+        /// client derives for the resolved DID.
+        Identified {
+            credential_id: Base64UrlSafeData,
+            challenge: Base64UrlSafeData,
+            issued_at: u64,
+        },
+        /// A new user, only stored once the ceremony finishes.
+        Registering {
+            did: String,
+            registration: PasskeyRegistration,
+            challenge: Base64UrlSafeData,
+            issued_at: u64,
+        },
+        AddingCredential {
+            registration: PasskeyRegistration,
+            challenge: Base64UrlSafeData,
+            issued_at: u64,
+        },
     }
 
-    impl Default for AppSessionState {
-        fn default() -> Self {
-            Self::Anonymous
+    impl AppSession {
+        /// Whether a user is logged in, possibly while adding a credential.
+        pub(crate) fn is_authenticated(&self) -> bool {
+            self.user_id.is_some()
+                && matches!(
+                    self.state,
+                    AppSessionState::Authenticated | AppSessionState::AddingCredential { .. }
+                )
+        }
+    }
+
+    impl AppSessionState {
+        /// The step and challenge of the ceremony in progress and when it was
+        /// issued.
+        fn ceremony(&self) -> Option<(&'static str, &[u8], u64)> {
+            let (step, challenge, issued_at) = match self {
+                Self::Authenticating {
+                    challenge,
+                    issued_at,
+                    ..
+                } => ("authenticating", challenge, issued_at),
+                Self::DiscoverableAuthenticating {
+                    challenge,
+                    issued_at,
+                    ..
+                } => ("discoverable_authenticating", challenge, issued_at),
+                Self::Identified {
+                    challenge,
+                    issued_at,
+                    ..
+                } => ("identified", challenge, issued_at),
+                Self::Registering {
+                    challenge,
+                    issued_at,
+                    ..
+                } => ("registering", challenge, issued_at),
+                Self::AddingCredential {
+                    challenge,
+                    issued_at,
+                    ..
+                } => ("adding_credential", challenge, issued_at),
+                Self::Anonymous | Self::Authenticated => return None,
+            };
+
+            Some((step, challenge.as_slice(), *issued_at))
+        }
+    }
+
+    /// Remembers the challenges of finished ceremony steps until they expire,
+    /// as concurrent requests of a session each read their own copy of it.
+    pub(crate) struct Ceremonies {
+        expiry: u64,
+        finished: Mutex<HashMap<(&'static str, Vec<u8>), u64>>,
+    }
+
+    impl Ceremonies {
+        pub(crate) fn new(expiry: Duration) -> Self {
+            Self {
+                expiry: expiry.as_secs(),
+                finished: Mutex::default(),
+            }
+        }
+
+        pub(crate) fn expiry(&self) -> u64 {
+            self.expiry
+        }
+
+        /// Rejects a ceremony issued at `issued_at` once the expiry has passed.
+        pub(crate) fn check_expiry(&self, issued_at: u64) -> Result<()> {
+            if store::now().saturating_sub(issued_at) >= self.expiry {
+                return Err(Error::with_code(
+                    ErrorCode::CeremonyExpired,
+                    "ceremony expired",
+                ));
+            }
+
+            Ok(())
+        }
+
+        fn finish(&self, step: &'static str, challenge: &[u8], issued_at: u64) -> Result<()> {
+            let now = store::now();
+            let mut finished = self.finished.lock().map_err(Error::from_poison)?;
+
+            // Expired ceremonies are rejected without looking them up
+            finished.retain(|_, issued_at| now.saturating_sub(*issued_at) < self.expiry);
+
+            if finished
+                .insert((step, challenge.to_vec()), issued_at)
+                .is_some()
+            {
+                return Err(Error::with_code(
+                    ErrorCode::NoCeremony,
+                    "ceremony already finished",
+                ));
+            }
+
+            Ok(())
         }
     }
 
@@ -75,7 +205,7 @@ mod session {
     }
 
     impl Session {
-        pub(crate) async fn read(self) -> Result<AppSession> {
+        pub(crate) async fn read(&self) -> Result<AppSession> {
             let session = self
                 .session
                 .get(SESSION_KEY)
@@ -97,6 +227,93 @@ mod session {
 
             Ok(data)
         }
+
+        /// Ends the ceremony in progress, so that a finish request consumes it
+        /// whether it succeeds or not. A user adding a credential stays logged
+        /// in, whether the new credential makes it or not.
+        pub(crate) async fn take_ceremony(
+            &mut self,
+            ceremonies: &Ceremonies,
+        ) -> Result<AppSession> {
+            let (step, challenge, issued_at) = self
+                .data
+                .state
+                .ceremony()
+                .or_code(ErrorCode::NoCeremony, "no ceremony in progress")?;
+
+            let finished = ceremonies.finish(step, challenge, issued_at);
+
+            let next = match self.data.state {
+                AppSessionState::AddingCredential { .. } => AppSession {
+                    state: AppSessionState::Authenticated,
+                    user_id: self.data.user_id,
+                },
+                _ => AppSession::default(),
+            };
+
+            let data = std::mem::replace(&mut self.data, next);
+
+            self.session
+                .insert(SESSION_KEY, &self.data)
+                .await
+                .context("failed to update session")?;
+
+            finished?;
+
+            Ok(data)
+        }
+
+        /// Logs the user in under a new session id, so that an id planted
+        /// before the login can't be used to ride on it.
+        pub(crate) async fn login(self, user_id: Uuid) -> Result<AppSession> {
+            self.session
+                .cycle_id()
+                .await
+                .context("failed to cycle session id")?;
+
+            self.write(|data| {
+                data.user_id = Some(user_id);
+                data.state = AppSessionState::Authenticated;
+            })
+            .await
+        }
+
+        pub(crate) async fn clear(self) -> Result<()> {
+            self.session
+                .flush()
+                .await
+                .context("failed to clear session")?;
+
+            Ok(())
+        }
+    }
+
+    /// Extracts the id of the logged in user, rejecting any session that did
+    /// not complete an authentication ceremony. A pending credential addition
+    /// leaves the user logged in, as the browser may never finish it.
+    #[derive(Debug)]
+    pub(crate) struct Authenticated(pub(crate) Uuid);
+
+    impl<S> FromRequestParts<S> for Authenticated
+    where
+        S: Send + Sync,
+    {
+        type Rejection = Error;
+
+        async fn from_request_parts(
+            req: &mut Parts,
+            state: &S,
+        ) -> std::result::Result<Self, Self::Rejection> {
+            let session = Session::from_request_parts(req, state).await?;
+
+            match session.data.user_id {
+                Some(user_id) if session.data.is_authenticated() => Ok(Self(user_id)),
+                _ => Err(Error::with_code(
+                    ErrorCode::Unauthenticated,
+                    "authentication required",
+                )),
+            }
+        }
     }
 
     impl Debug for Session {
@@ -109,22 +326,20 @@ mod session {
     where
         S: Send + Sync,
     {
-        type Rejection = (StatusCode, &'static str);
+        type Rejection = Error;
 
         async fn from_request_parts(
             req: &mut Parts,
             state: &S,
         ) -> std::result::Result<Self, Self::Rejection> {
-            let session = tower_sessions::Session::from_request_parts(req, state).await?;
+            let session = tower_sessions::Session::from_request_parts(req, state)
+                .await
+                .map_err(|(_, msg)| Error::new(msg))?;
+
             let data = session
                 .get(SESSION_KEY)
                 .await
-                .map_err(|_| {
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "failed to get session data",
-                    )
-                })?
+                .context("failed to get session data")?
                 .unwrap_or_default();
 
             Ok(Self { data, session })
@@ -133,39 +348,73 @@ mod session {
 }
 
 impl App {
-    pub fn new(id: &str, endpoint: &str) -> Result<Self> {
-        let url = format!("https://{endpoint}");
-        let origin = Url::parse(&url).context("failed to construct rp origin url")?;
-
-        Ok(Self {
-            id: id.to_owned(),
-            origin,
-        })
+    pub fn new(config: Config) -> Self {
+        Self { config }
     }
 
     pub fn into_router(self) -> Result<Router> {
-        let database = Mutex::new(HashMap::new());
+        self.config.validate()?;
+
+        let storage = match &self.config.database {
+            Some(path) => Storage::Sqlite(path.clone()),
+            None => Storage::Memory,
+        };
+
+        info!(storage = ?storage, "opening user storage");
 
-        let webauthn = WebauthnBuilder::new(&self.id, &self.origin)
+        let users = storage.open()?;
+
+        let rp = &self.config.rp;
+        let webauthn = WebauthnBuilder::new(&rp.id, &rp.origin)
             .context("invalid webauthn configuration")?
-            .rp_name("WebAuthn Demo")
+            .rp_name(&rp.name)
             .build()
             .context("failed to build webauthn")?;
 
-        let state = Arc::new(AppState { database, webauthn });
+        let ceremonies = Ceremonies::new(self.config.session.ceremony_expiry());
+
+        let state = Arc::new(AppState {
+            users,
+            webauthn,
+            ceremonies,
+        });
 
         let session_key = Key::generate();
         let session_store = MemoryStore::default();
+        let expiry = Duration::try_from(self.config.session.expiry())
+            .context("session expiry out of range")?;
+
         let session_layer = SessionManagerLayer::new(session_store)
-            .with_expiry(Expiry::OnInactivity(Duration::seconds(300)))
+            .with_expiry(Expiry::OnInactivity(expiry))
             .with_signed(session_key);
 
         let router = Router::new()
             .route("/auth/start", post(Self::start_authentication))
             .route("/auth/finish", post(Self::finish_authentication))
+            .route(
+                "/auth/discover/start",
+                post(Self::start_discoverable_authentication),
+            )
+            .route(
+                "/auth/discover/finish",
+                post(Self::finish_discoverable_authentication),
+            )
+            .route(
+                "/auth/discover/verify",
+                post(Self::verify_discoverable_authentication),
+            )
+            .route("/auth/logout", post(Self::logout))
+            .route("/auth/me", get(Self::me))
+            .route("/account/delete", post(Self::delete_account))
+            .route("/keys/{did}", get(Self::lookup_keys))
             .route("/register/start", post(Self::start_registration))
             .route("/register/finish", post(Self::finish_registration))
-            .fallback_service(ServeDir::new("static"))
+            .route("/credentials/add/start", post(Self::start_add_credential))
+            .route("/credentials/add/finish", post(Self::finish_add_credential))
+            .route("/credentials", get(Self::list_credentials))
+            .route("/credentials/rename", post(Self::rename_credential))
+            .route("/credentials/revoke", post(Self::revoke_credential))
+            .fallback_service(ServeDir::new(&self.config.static_dir))
             .layer(TraceLayer::new_for_http())
             .layer(session_layer)
             .with_state(state);
@@ -177,122 +426,687 @@ impl App {
     async fn start_authentication(
         State(state): State<Arc<AppState>>,
         session: Session,
-        Json(req): Json<StartAuthenticationRequest>,
+        Body(req): Body<StartAuthenticationRequest>,
     ) -> Result<Json<StartAuthenticationResponse>> {
-        let (challenge, authentication, user_id) = {
-            let database = state.database.lock().map_err(Error::from_poison)?;
+        let user = state
+            .users
+            .get_by_did(&req.did)?
+            .or_code(ErrorCode::UserNotFound, "no such user")?;
 
-            let user = database.get(&req.did).context("no such user")?;
-            let passkey = user.passkey.as_ref().context("user has no passkey")?;
+        if user.credentials.is_empty() {
+            return Err(Error::with_code(
+                ErrorCode::CredentialNotFound,
+                "user has no passkey",
+            ));
+        }
 
-            let (challenge, authentication) = state
-                .webauthn
-                .start_passkey_authentication(&[passkey.to_owned()])
-                .context("failed to start passkey authentication")?;
+        let (challenge, authentication) = state
+            .webauthn
+            .start_passkey_authentication(&user.passkeys())
+            .context("failed to start passkey authentication")?;
 
-            let user_id = user.id;
+        let derivations = user
+            .credentials
+            .iter()
+            .map(|credential| CredentialDerivation {
+                credential_id: credential.id().into(),
+                derivation: credential.derivation,
+                algorithm: credential.algorithm,
+            })
+            .collect();
 
-            (challenge, authentication, user_id)
-        };
+        let user_id = user.id;
+        let challenge_bytes = challenge.public_key.challenge.clone();
 
         session
             .write(move |data| {
                 data.user_id = Some(user_id);
-                data.state = AppSessionState::Authenticating(authentication);
+                data.state = AppSessionState::Authenticating {
+                    authentication,
+                    challenge: challenge_bytes,
+                    issued_at: store::now(),
+                };
             })
             .await?;
 
-        Ok(Json(StartAuthenticationResponse { challenge }))
+        Ok(Json(StartAuthenticationResponse {
+            challenge,
+            derivations,
+        }))
     }
 
     #[instrument(skip(state))]
     async fn finish_authentication(
         State(state): State<Arc<AppState>>,
-        session: Session,
-        Json(req): Json<FinishAuthenticationRequest>,
+        mut session: Session,
+        Body(req): Body<FinishAuthenticationRequest>,
     ) -> Result<Json<FinishAuthenticationResponse>> {
-        let session = session.read().await?;
+        let data = session.take_ceremony(&state.ceremonies).await?;
 
-        if let AppSessionState::Authenticating(authentication) = session.state {
-            let _auth = state
+        if let AppSessionState::Authenticating {
+            authentication,
+            challenge,
+            issued_at,
+        } = data.state
+        {
+            state.ceremonies.check_expiry(issued_at)?;
+
+            let auth = state
                 .webauthn
                 .finish_passkey_authentication(&req.credential, &authentication)
-                .context("failed to finish passkey authentication")?;
+                .or_code(
+                    ErrorCode::AuthenticationFailed,
+                    "failed to finish passkey authentication",
+                )?;
+
+            let user_id = data
+                .user_id
+                .or_code(ErrorCode::NoCeremony, "no user id in session")?;
+            let user = state
+                .users
+                .get_by_id(user_id)?
+                .or_code(ErrorCode::UserNotFound, "no such user")?;
+
+            let credential = user
+                .credential(auth.cred_id())
+                .or_code(ErrorCode::CredentialNotFound, "no such credential")?;
+
+            let algorithm =
+                Self::check_signing_key(&user.did, &challenge, credential, &req.signing_key)?;
 
-            let user_id = session.user_id.context("no user id in session")?;
+            state.users.bind_signing_key(
+                user_id,
+                credential.id(),
+                algorithm,
+                &req.signing_key.did_key,
+            )?;
+            state.users.record_use(user_id, &auth)?;
+
+            session.login(user_id).await?;
+
+            info!(did = %user.did, "user is authenticated");
 
             Ok(Json(FinishAuthenticationResponse { user_id }))
         } else {
-            Err(Error::new("invalid session state"))
+            Err(Error::with_code(
+                ErrorCode::NoCeremony,
+                "no ceremony in progress",
+            ))
+        }
+    }
+
+    #[instrument(skip(state))]
+    async fn start_discoverable_authentication(
+        State(state): State<Arc<AppState>>,
+        session: Session,
+        Body(req): Body<StartDiscoverableAuthenticationRequest>,
+    ) -> Result<Json<StartDiscoverableAuthenticationResponse>> {
+        // Autofill starts this on every page load, which must not log out
+        if req.conditional && session.read().await?.is_authenticated() {
+            return Err(Error::with_code(
+                ErrorCode::AlreadyAuthenticated,
+                "already logged in",
+            ));
+        }
+
+        let (mut challenge, authentication) = state
+            .webauthn
+            .start_discoverable_authentication()
+            .context("failed to start discoverable authentication")?;
+
+        // Started from a button, the client decides whether to use autofill
+        challenge.mediation = None;
+
+        let challenge_bytes = challenge.public_key.challenge.clone();
+
+        session
+            .write(move |data| {
+                data.user_id = None;
+                data.state = AppSessionState::DiscoverableAuthenticating {
+                    authentication,
+                    challenge: challenge_bytes,
+                    issued_at: store::now(),
+                };
+            })
+            .await?;
+
+        Ok(Json(StartDiscoverableAuthenticationResponse {
+            challenge,
+            expires_in_secs: state.ceremonies.expiry(),
+        }))
+    }
+
+    /// Resolves the user from the credential's user handle. The client only
+    /// learns the DID to derive its keys from here, so the session is logged
+    /// in once it proves the derived key in a second request.
+    #[instrument(skip(state))]
+    async fn finish_discoverable_authentication(
+        State(state): State<Arc<AppState>>,
+        mut session: Session,
+        Body(req): Body<FinishDiscoverableAuthenticationRequest>,
+    ) -> Result<Json<FinishDiscoverableAuthenticationResponse>> {
+        let data = session.take_ceremony(&state.ceremonies).await?;
+
+        let AppSessionState::DiscoverableAuthenticating {
+            authentication,
+            challenge,
+            issued_at,
+        } = data.state
+        else {
+            return Err(Error::with_code(
+                ErrorCode::NoCeremony,
+                "no ceremony in progress",
+            ));
+        };
+
+        state.ceremonies.check_expiry(issued_at)?;
+
+        let (user_id, _) = state
+            .webauthn
+            .identify_discoverable_authentication(&req.credential)
+            .or_code(ErrorCode::CeremonyFailed, "no user handle in credential")?;
+
+        let user = state
+            .users
+            .get_by_id(user_id)?
+            .or_code(ErrorCode::UserNotFound, "no such user")?;
+
+        let keys: Vec<DiscoverableKey> = user
+            .credentials
+            .iter()
+            .map(|credential| DiscoverableKey::from(&credential.passkey))
+            .collect();
+
+        let auth = state
+            .webauthn
+            .finish_discoverable_authentication(&req.credential, authentication, &keys)
+            .or_code(
+                ErrorCode::AuthenticationFailed,
+                "failed to finish discoverable authentication",
+            )?;
+
+        let credential = user
+            .credential(auth.cred_id())
+            .or_code(ErrorCode::CredentialNotFound, "no such credential")?;
+
+        state.users.record_use(user_id, &auth)?;
+
+        let credential_id = auth.cred_id().clone().into();
+
+        session
+            .write(move |data| {
+                data.user_id = Some(user_id);
+                data.state = AppSessionState::Identified {
+                    credential_id,
+                    challenge,
+                    issued_at,
+                };
+            })
+            .await?;
+
+        info!(did = %user.did, "user is identified");
+
+        Ok(Json(FinishDiscoverableAuthenticationResponse {
+            user_id,
+            did: user.did.clone(),
+            derivation: credential.derivation,
+            algorithm: credential.algorithm,
+        }))
+    }
+
+    /// Logs in the user identified by a discoverable credential, once the
+    /// derived key checks out as with [`Self::finish_authentication`].
+    #[instrument(skip(state))]
+    async fn verify_discoverable_authentication(
+        State(state): State<Arc<AppState>>,
+        mut session: Session,
+        Body(req): Body<VerifyDiscoverableAuthenticationRequest>,
+    ) -> Result<Json<VerifyDiscoverableAuthenticationResponse>> {
+        let data = session.take_ceremony(&state.ceremonies).await?;
+
+        let AppSessionState::Identified {
+            credential_id,
+            challenge,
+            issued_at,
+        } = data.state
+        else {
+            return Err(Error::with_code(
+                ErrorCode::NoCeremony,
+                "no ceremony in progress",
+            ));
+        };
+
+        state.ceremonies.check_expiry(issued_at)?;
+
+        let user_id = data
+            .user_id
+            .or_code(ErrorCode::NoCeremony, "no user id in session")?;
+        let user = state
+            .users
+            .get_by_id(user_id)?
+            .or_code(ErrorCode::UserNotFound, "no such user")?;
+
+        let credential = user
+            .credential(&credential_id)
+            .or_code(ErrorCode::CredentialNotFound, "no such credential")?;
+
+        let algorithm =
+            Self::check_signing_key(&user.did, &challenge, credential, &req.signing_key)?;
+
+        state.users.bind_signing_key(
+            user_id,
+            &credential_id,
+            algorithm,
+            &req.signing_key.did_key,
+        )?;
+
+        session.login(user_id).await?;
+
+        info!(did = %user.did, "user is authenticated");
+
+        Ok(Json(VerifyDiscoverableAuthenticationResponse { user_id }))
+    }
+
+    #[instrument(skip(state))]
+    async fn me(
+        State(state): State<Arc<AppState>>,
+        Authenticated(user_id): Authenticated,
+    ) -> Result<Json<MeResponse>> {
+        let user = state
+            .users
+            .get_by_id(user_id)?
+            .or_code(ErrorCode::UserNotFound, "no such user")?;
+
+        Ok(Json(MeResponse {
+            user_id: user.id,
+            did: user.did,
+        }))
+    }
+
+    #[instrument(skip(state))]
+    async fn lookup_keys(
+        State(state): State<Arc<AppState>>,
+        Path(did): Path<String>,
+    ) -> Result<Json<LookupKeysResponse>> {
+        let user = state
+            .users
+            .get_by_did(&did)?
+            .or_code(ErrorCode::UserNotFound, "no such user")?;
+        let signing_keys: Vec<_> = user
+            .credentials
+            .iter()
+            .filter_map(|credential| credential.signing_key.clone())
+            .collect();
+
+        if signing_keys.is_empty() {
+            return Err(Error::with_code(
+                ErrorCode::KeysNotFound,
+                "no keys bound to user",
+            ));
         }
+
+        // The rotation key was derived next to the registering credential's
+        // signing key, so it is revoked along with that credential
+        let rotation_key = user
+            .keys
+            .filter(|keys| signing_keys.contains(&keys.signing_key))
+            .map(|keys| keys.rotation_key);
+
+        Ok(Json(LookupKeysResponse {
+            did: user.did,
+            signing_keys,
+            rotation_key,
+        }))
+    }
+
+    #[instrument]
+    async fn logout(session: Session) -> Result<Json<LogoutResponse>> {
+        // A started login holds the target DID's user id, which is also its
+        // WebAuthn user handle
+        let data = session.read().await?;
+        let user_id = data.user_id.filter(|_| data.is_authenticated());
+
+        session.clear().await?;
+
+        info!(user_id = ?user_id, "user is logged out");
+
+        Ok(Json(LogoutResponse { user_id }))
+    }
+
+    /// Deletes the logged in user with its credentials and keys, which frees
+    /// the DID, and logs out.
+    #[instrument(skip(state))]
+    async fn delete_account(
+        State(state): State<Arc<AppState>>,
+        Authenticated(user_id): Authenticated,
+        session: Session,
+    ) -> Result<Json<DeleteAccountResponse>> {
+        state.users.delete(user_id)?;
+        session.clear().await?;
+
+        info!("user is deleted on the backend");
+
+        Ok(Json(DeleteAccountResponse { user_id }))
     }
 
     #[instrument(skip(state))]
     async fn start_registration(
         State(state): State<Arc<AppState>>,
         session: Session,
-        Json(req): Json<StartRegistrationRequest>,
+        Body(req): Body<StartRegistrationRequest>,
     ) -> Result<Json<StartRegistrationResponse>> {
         let user_id = Uuid::new_v4();
 
         info!(user_id = ?user_id, "registering user");
 
-        let (challenge, registration) = state
+        // Checked again when the user is stored
+        if state.users.get_by_did(&req.did)?.is_some() {
+            return Err(Error::with_code(
+                ErrorCode::UserExists,
+                "user already exists",
+            ));
+        }
+
+        let (mut challenge, registration) = state
             .webauthn
             .start_passkey_registration(user_id, &req.did, &req.did, None)
             .context("failed to start passkey registration")?;
 
+        Self::prefer_discoverable(&mut challenge);
+
+        // Kept to verify the proofs of possession for the derived keys
+        let challenge_bytes = challenge.public_key.challenge.clone();
+
         session
             .write(move |data| {
                 data.user_id = Some(user_id);
-                data.state = AppSessionState::Registering(registration);
+                data.state = AppSessionState::Registering {
+                    did: req.did,
+                    registration,
+                    challenge: challenge_bytes,
+                    issued_at: store::now(),
+                };
             })
             .await?;
 
-        let mut database = state.database.lock().map_err(Error::from_poison)?;
+        Ok(Json(StartRegistrationResponse { challenge, user_id }))
+    }
+
+    #[instrument(skip(state))]
+    async fn finish_registration(
+        State(state): State<Arc<AppState>>,
+        mut session: Session,
+        Body(req): Body<FinishRegistrationRequest>,
+    ) -> Result<Json<FinishRegistrationResponse>> {
+        let data = session.take_ceremony(&state.ceremonies).await?;
+        let user_id = data
+            .user_id
+            .or_code(ErrorCode::NoCeremony, "no user id in session")?;
 
-        let name = req.did.clone();
+        let AppSessionState::Registering {
+            did,
+            registration,
+            challenge,
+            issued_at,
+        } = data.state
+        else {
+            return Err(Error::with_code(
+                ErrorCode::NoCeremony,
+                "no ceremony in progress",
+            ));
+        };
 
-        if let Entry::Vacant(entry) = database.entry(name.clone()) {
-            entry.insert(User {
-                id: user_id,
-                passkey: None,
-            });
+        state.ceremonies.check_expiry(issued_at)?;
 
-            Ok(Json(StartRegistrationResponse { challenge, user_id }))
-        } else {
-            Err(Error::new("user already exists"))
-        }
+        let passkey = state
+            .webauthn
+            .finish_passkey_registration(&req.credential, &registration)
+            .or_code(
+                ErrorCode::CeremonyFailed,
+                "failed to finish passkey registration",
+            )?;
+
+        let algorithm = Self::verify_proof(&did, &challenge, &req.signing_key)?;
+        Self::verify_proof(&did, &challenge, &req.rotation_key)?;
+
+        let credential = Credential::new(
+            passkey,
+            req.derivation,
+            algorithm,
+            req.signing_key.did_key.clone(),
+        );
+
+        state.users.insert(User {
+            id: user_id,
+            did,
+            keys: Some(DidKeys {
+                signing_key: req.signing_key.did_key,
+                rotation_key: req.rotation_key.did_key,
+            }),
+            credentials: vec![credential],
+        })?;
+
+        info!("user is registered on the backend");
+
+        Ok(Json(FinishRegistrationResponse { success: true }))
     }
 
     #[instrument(skip(state))]
-    async fn finish_registration(
+    async fn start_add_credential(
         State(state): State<Arc<AppState>>,
+        Authenticated(user_id): Authenticated,
         session: Session,
-        Json(req): Json<FinishRegistrationRequest>,
-    ) -> Result<Json<FinishRegistrationResponse>> {
-        let session = session.read().await?;
-        let user_id = session.user_id.context("user not found")?;
+    ) -> Result<Json<StartAddCredentialResponse>> {
+        let user = state
+            .users
+            .get_by_id(user_id)?
+            .or_code(ErrorCode::UserNotFound, "no such user")?;
 
-        if let AppSessionState::Registering(registration) = session.state {
-            let passkey = state
-                .webauthn
-                .finish_passkey_registration(&req.credential, &registration)
-                .context("failed to finish passkey registration")?;
+        let exclude_credentials = user
+            .credentials
+            .iter()
+            .map(|credential| credential.passkey.cred_id().clone())
+            .collect();
 
-            let mut database = state.database.lock().map_err(Error::from_poison)?;
+        let (mut challenge, registration) = state
+            .webauthn
+            .start_passkey_registration(user.id, &user.did, &user.did, Some(exclude_credentials))
+            .context("failed to start passkey registration")?;
 
-            database
-                .values_mut()
-                .find(|user| user.id == user_id)
-                .context(format!("no user found for uuid {user_id}"))?
-                .passkey = Some(passkey);
+        Self::prefer_discoverable(&mut challenge);
 
-            info!("user is registered on the backend");
+        let challenge_bytes = challenge.public_key.challenge.clone();
 
-            Ok(Json(FinishRegistrationResponse { success: true }))
-        } else {
-            Err(Error::new("invalid session state"))
+        session
+            .write(move |data| {
+                data.state = AppSessionState::AddingCredential {
+                    registration,
+                    challenge: challenge_bytes,
+                    issued_at: store::now(),
+                }
+            })
+            .await?;
+
+        Ok(Json(StartAddCredentialResponse { challenge }))
+    }
+
+    #[instrument(skip(state))]
+    async fn finish_add_credential(
+        State(state): State<Arc<AppState>>,
+        mut session: Session,
+        Body(req): Body<FinishAddCredentialRequest>,
+    ) -> Result<Json<FinishAddCredentialResponse>> {
+        let data = session.take_ceremony(&state.ceremonies).await?;
+        let user_id = data
+            .user_id
+            .or_code(ErrorCode::NoCeremony, "no user id in session")?;
+
+        let AppSessionState::AddingCredential {
+            registration,
+            challenge,
+            issued_at,
+        } = data.state
+        else {
+            return Err(Error::with_code(
+                ErrorCode::NoCeremony,
+                "no ceremony in progress",
+            ));
+        };
+
+        state.ceremonies.check_expiry(issued_at)?;
+
+        let user = state
+            .users
+            .get_by_id(user_id)?
+            .or_code(ErrorCode::UserNotFound, "no such user")?;
+
+        let passkey = state
+            .webauthn
+            .finish_passkey_registration(&req.credential, &registration)
+            .or_code(
+                ErrorCode::CeremonyFailed,
+                "failed to finish passkey registration",
+            )?;
+        let credential_id = passkey.cred_id().clone().into();
+
+        // Every authenticator has its own PRF secret and hence its own key
+        let algorithm = Self::verify_proof(&user.did, &challenge, &req.signing_key)?;
+
+        let credential =
+            Credential::new(passkey, req.derivation, algorithm, req.signing_key.did_key);
+        state.users.attach_credential(user_id, credential)?;
+
+        info!("credential is added on the backend");
+
+        Ok(Json(FinishAddCredentialResponse { credential_id }))
+    }
+
+    #[instrument(skip(state))]
+    async fn list_credentials(
+        State(state): State<Arc<AppState>>,
+        Authenticated(user_id): Authenticated,
+    ) -> Result<Json<ListCredentialsResponse>> {
+        let user = state
+            .users
+            .get_by_id(user_id)?
+            .or_code(ErrorCode::UserNotFound, "no such user")?;
+
+        let credentials = user.credentials.iter().map(Self::credential_info).collect();
+
+        Ok(Json(ListCredentialsResponse { credentials }))
+    }
+
+    #[instrument(skip(state))]
+    async fn rename_credential(
+        State(state): State<Arc<AppState>>,
+        Authenticated(user_id): Authenticated,
+        Body(req): Body<RenameCredentialRequest>,
+    ) -> Result<Json<RenameCredentialResponse>> {
+        let nickname = req.nickname.trim();
+
+        state.users.set_nickname(
+            user_id,
+            &req.credential_id,
+            (!nickname.is_empty()).then_some(nickname),
+        )?;
+
+        let user = state
+            .users
+            .get_by_id(user_id)?
+            .or_code(ErrorCode::UserNotFound, "no such user")?;
+
+        let credential = user
+            .credential(&req.credential_id)
+            .or_code(ErrorCode::CredentialNotFound, "no such credential")?;
+
+        Ok(Json(RenameCredentialResponse {
+            credential: Self::credential_info(credential),
+        }))
+    }
+
+    #[instrument(skip(state))]
+    async fn revoke_credential(
+        State(state): State<Arc<AppState>>,
+        Authenticated(user_id): Authenticated,
+        Body(req): Body<RevokeCredentialRequest>,
+    ) -> Result<Json<RevokeCredentialResponse>> {
+        state.users.remove_credential(user_id, &req.credential_id)?;
+
+        info!("credential is revoked on the backend");
+
+        let user = state
+            .users
+            .get_by_id(user_id)?
+            .or_code(ErrorCode::UserNotFound, "no such user")?;
+        let credentials = user.credentials.iter().map(Self::credential_info).collect();
+
+        Ok(Json(RevokeCredentialResponse { credentials }))
+    }
+
+    /// Checks that the client holds the derived key, by its signature over the
+    /// ceremony challenge, and returns the key's algorithm.
+    fn verify_proof(did: &str, challenge: &[u8], key: &KeyProof) -> Result<Algorithm> {
+        keygen::proof::verify(&key.did_key, did, challenge, &key.signature)
+            .and_then(|()| didkey::decode(&key.did_key))
+            .map(|(algorithm, _)| algorithm)
+            .map_err(|err| {
+                warn!(err = ?err, did_key = %key.did_key, "invalid proof of possession");
+
+                Error::with_code(ErrorCode::InvalidProof, "invalid proof of possession")
+            })
+    }
+
+    /// Checks the proof for the derived signing key and that it is the key
+    /// bound to the credential, returning the algorithm of the derived keys.
+    fn check_signing_key(
+        did: &str,
+        challenge: &[u8],
+        credential: &Credential,
+        key: &KeyProof,
+    ) -> Result<Algorithm> {
+        let algorithm = Self::verify_proof(did, challenge, key)?;
+
+        match &credential.signing_key {
+            Some(signing_key) if *signing_key != key.did_key => {
+                warn!(
+                    did = %did,
+                    expected = %signing_key,
+                    actual = %key.did_key,
+                    "derived key does not match the registered key"
+                );
+
+                return Err(Error::with_code(
+                    ErrorCode::KeyMismatch,
+                    "derived key does not match the registered key",
+                ));
+            }
+            Some(_) => {}
+            None => {
+                // Credentials from before keys were bound learn theirs on
+                // first use
+                warn!(did = %did, "binding derived key to credential");
+            }
+        }
+
+        Ok(algorithm)
+    }
+
+    /// Asks for a discoverable credential, which webauthn-rs discourages for
+    /// passkeys, so that the user can log in without entering the DID.
+    fn prefer_discoverable(challenge: &mut CreationChallengeResponse) {
+        if let Some(selection) = &mut challenge.public_key.authenticator_selection {
+            selection.resident_key = Some(ResidentKeyRequirement::Preferred);
+        }
+    }
+
+    fn credential_info(credential: &Credential) -> CredentialInfo {
+        CredentialInfo {
+            credential_id: credential.id().into(),
+            nickname: credential.nickname.clone(),
+            created_at: credential.created_at,
+            last_used_at: credential.last_used_at,
+            backup_state: credential.backup_state(),
+            derivation: credential.derivation,
         }
     }
 }
@@ -0,0 +1,230 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::Parser;
+use serde::Deserialize;
+use url::Url;
+
+use crate::error::{Context, Error, Result};
+
+/// Server configuration, loaded from an optional TOML file and overridden by
+/// environment variables and command line flags (in that order).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub rp: RelyingParty,
+    pub bind: SocketAddr,
+    pub tls: Tls,
+    pub static_dir: PathBuf,
+    pub session: Session,
+    /// SQLite database for users; kept in memory if unset.
+    pub database: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelyingParty {
+    pub id: String,
+    pub origin: Url,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tls {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Session {
+    /// Seconds of inactivity after which a session expires.
+    pub expiry_secs: u64,
+    /// Seconds after which a started ceremony can no longer be finished, less
+    /// than `expiry_secs` so that it expires before the session holding it.
+    pub ceremony_expiry_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rp: RelyingParty::default(),
+            bind: SocketAddr::from(([127, 0, 0, 1], 9999)),
+            tls: Tls::default(),
+            static_dir: PathBuf::from("static"),
+            session: Session::default(),
+            database: None,
+        }
+    }
+}
+
+impl Default for RelyingParty {
+    fn default() -> Self {
+        Self {
+            id: "localhost".to_owned(),
+            origin: Url::parse("https://localhost:9999").expect("valid default origin"),
+            name: "WebAuthn Demo".to_owned(),
+        }
+    }
+}
+
+impl Default for Tls {
+    fn default() -> Self {
+        Self {
+            cert: PathBuf::from("certs/cert.pem"),
+            key: PathBuf::from("certs/key.pem"),
+        }
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            expiry_secs: 300,
+            ceremony_expiry_secs: 120,
+        }
+    }
+}
+
+impl Session {
+    pub fn expiry(&self) -> Duration {
+        Duration::from_secs(self.expiry_secs)
+    }
+
+    pub fn ceremony_expiry(&self) -> Duration {
+        Duration::from_secs(self.ceremony_expiry_secs)
+    }
+}
+
+/// Command line flags, each of which can also be set by environment variable.
+#[derive(Debug, Parser)]
+#[command(version, about = "WebAuthn PRF demo server")]
+struct Args {
+    /// TOML config file
+    #[arg(long, short, env = "SERVER_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// WebAuthn relying party id, the domain of the origin
+    #[arg(long, env = "SERVER_RP_ID")]
+    rp_id: Option<String>,
+
+    /// WebAuthn relying party origin, e.g. https://localhost:9999
+    #[arg(long, env = "SERVER_RP_ORIGIN")]
+    rp_origin: Option<Url>,
+
+    /// WebAuthn relying party name shown by authenticators
+    #[arg(long, env = "SERVER_RP_NAME")]
+    rp_name: Option<String>,
+
+    /// Address to listen on
+    #[arg(long, env = "SERVER_BIND")]
+    bind: Option<SocketAddr>,
+
+    /// TLS certificate chain (PEM)
+    #[arg(long, env = "SERVER_TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// TLS private key (PEM)
+    #[arg(long, env = "SERVER_TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// Directory with the static files and WASM client
+    #[arg(long, env = "SERVER_STATIC_DIR")]
+    static_dir: Option<PathBuf>,
+
+    /// Session inactivity expiry in seconds
+    #[arg(long, env = "SERVER_SESSION_EXPIRY")]
+    session_expiry: Option<u64>,
+
+    /// Seconds to finish a started registration or login
+    #[arg(long, env = "SERVER_CEREMONY_EXPIRY")]
+    ceremony_expiry: Option<u64>,
+
+    /// SQLite database for users; kept in memory if unset
+    #[arg(long, env = "SERVER_DATABASE")]
+    database: Option<PathBuf>,
+}
+
+impl Config {
+    /// Loads the config file named by `--config` (if any) and applies the
+    /// environment and command line overrides.
+    pub fn load() -> Result<Self> {
+        let args = Args::parse();
+
+        let mut config = match &args.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+
+        args.apply(&mut config);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.session.ceremony_expiry_secs >= self.session.expiry_secs {
+            return Err(Error::new(
+                "ceremony expiry must be shorter than the session expiry",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .context(format!("failed to read config file {}", path.display()))?;
+
+        let config = toml::from_str(&contents)
+            .context(format!("malformed config file {}", path.display()))?;
+
+        Ok(config)
+    }
+}
+
+impl Args {
+    fn apply(self, config: &mut Config) {
+        if let Some(id) = self.rp_id {
+            config.rp.id = id;
+        }
+
+        if let Some(origin) = self.rp_origin {
+            config.rp.origin = origin;
+        }
+
+        if let Some(name) = self.rp_name {
+            config.rp.name = name;
+        }
+
+        if let Some(bind) = self.bind {
+            config.bind = bind;
+        }
+
+        if let Some(cert) = self.tls_cert {
+            config.tls.cert = cert;
+        }
+
+        if let Some(key) = self.tls_key {
+            config.tls.key = key;
+        }
+
+        if let Some(static_dir) = self.static_dir {
+            config.static_dir = static_dir;
+        }
+
+        if let Some(expiry_secs) = self.session_expiry {
+            config.session.expiry_secs = expiry_secs;
+        }
+
+        if let Some(ceremony_expiry_secs) = self.ceremony_expiry {
+            config.session.ceremony_expiry_secs = ceremony_expiry_secs;
+        }
+
+        if let Some(database) = self.database {
+            config.database = Some(database);
+        }
+    }
+}
@@ -1,24 +1,87 @@
+use std::fmt::{self, Debug, Display};
 use std::sync::PoisonError;
 
 pub(crate) use anyhow::Context;
+use api::{ErrorCode, ErrorResponse};
 use axum::{
     Json,
+    extract::rejection::JsonRejection,
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use tracing::error;
+use tracing::{error, warn};
 
-#[derive(Debug)]
-pub struct Error(anyhow::Error);
+pub struct Error {
+    err: anyhow::Error,
+    code: Option<ErrorCode>,
+}
 
 impl Error {
     pub(crate) fn new(msg: &'static str) -> Self {
-        Error(anyhow::anyhow!(msg))
+        Error::from(anyhow::anyhow!(msg))
+    }
+
+    /// An error that is reported to the client with its message and a stable
+    /// code, instead of being masked as an internal error.
+    pub(crate) fn with_code<M>(code: ErrorCode, msg: M) -> Self
+    where
+        M: Display + Debug + Send + Sync + 'static,
+    {
+        Error {
+            err: anyhow::Error::msg(msg),
+            code: Some(code),
+        }
     }
 
     pub(crate) fn from_poison<T>(_msg: PoisonError<T>) -> Self {
         Self::new("failed to acquire lock")
     }
+
+    fn code(&self) -> ErrorCode {
+        match self.code {
+            Some(code) => code,
+            None if self.err.is::<JsonRejection>() => ErrorCode::BadRequest,
+            None => ErrorCode::Internal,
+        }
+    }
+}
+
+/// Like [`Context`], but reports the error to the client with its message and
+/// a stable code.
+pub(crate) trait ErrorCodeExt<T> {
+    fn or_code<M>(self, code: ErrorCode, msg: M) -> Result<T>
+    where
+        M: Display + Debug + Send + Sync + 'static;
+}
+
+impl<T> ErrorCodeExt<T> for Option<T> {
+    fn or_code<M>(self, code: ErrorCode, msg: M) -> Result<T>
+    where
+        M: Display + Debug + Send + Sync + 'static,
+    {
+        self.ok_or_else(|| Error::with_code(code, msg))
+    }
+}
+
+impl<T, E> ErrorCodeExt<T> for std::result::Result<T, E>
+where
+    E: Into<anyhow::Error>,
+{
+    fn or_code<M>(self, code: ErrorCode, msg: M) -> Result<T>
+    where
+        M: Display + Debug + Send + Sync + 'static,
+    {
+        self.map_err(|err| Error {
+            err: err.into().context(msg),
+            code: Some(code),
+        })
+    }
+}
+
+impl Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.err, f)
+    }
 }
 
 impl<E> From<E> for Error
@@ -26,21 +89,33 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Error(err.into())
+        Error {
+            err: err.into(),
+            code: None,
+        }
     }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        error!("{}", self.0);
+        let code = self.code();
+
+        let error = match code {
+            ErrorCode::Internal => {
+                error!("{:#}", self.err);
+                "Internal server error".to_owned()
+            }
+            _ => {
+                warn!(code = %code, "{:#}", self.err);
+                self.err.to_string()
+            }
+        };
 
-        let res = (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": "Internal server error"})),
-        );
+        let status =
+            StatusCode::from_u16(code.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
 
-        res.into_response()
+        (status, Json(ErrorResponse { error, code })).into_response()
     }
 }
 
-pub(crate) type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = std::result::Result<T, Error>;
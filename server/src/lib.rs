@@ -0,0 +1,11 @@
+//! The relying party of the demo: runs the WebAuthn ceremonies, binds the
+//! derived keys to DIDs and manages the users' credentials.
+
+mod app;
+pub mod config;
+mod error;
+mod store;
+
+pub use app::App;
+pub use config::Config;
+pub use error::{Error, Result};
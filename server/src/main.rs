@@ -1,15 +1,9 @@
-use std::path::PathBuf;
-
-use app::App;
+use anyhow::Context;
 use axum_server::tls_rustls::RustlsConfig;
+use server::{App, Config, Result};
 use tracing::info;
 use tracing_subscriber::prelude::*;
 
-use crate::error::Result;
-
-mod app;
-mod error;
-
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::registry()
@@ -27,23 +21,18 @@ async fn main() -> Result<()> {
         )
         .init();
 
-    let name = "localhost";
-    let port = 9999;
-
-    let bind = format!("localhost:{port}");
-    info!(bind, "binding server");
+    let config = Config::load()?;
 
-    let app = App::new(name, &bind)?;
+    info!(bind = %config.bind, origin = %config.rp.origin, "binding server");
 
-    let bind = format!("127.0.0.1:{port}");
+    let tls = RustlsConfig::from_pem_file(&config.tls.cert, &config.tls.key)
+        .await
+        .context("failed to load tls certificate")?;
 
-    let config = RustlsConfig::from_pem_file(
-        PathBuf::from("certs/cert.pem"),
-        PathBuf::from("certs/key.pem"),
-    )
-    .await?;
+    let bind = config.bind;
+    let app = App::new(config);
 
-    axum_server::bind_rustls(bind.parse()?, config)
+    axum_server::bind_rustls(bind, tls)
         .serve(app.into_router()?.into_make_service())
         .await?;
 
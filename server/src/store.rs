@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use keygen::{Algorithm, Version};
+use uuid::Uuid;
+use webauthn_rs::prelude::{AuthenticationResult, Passkey};
+
+use crate::error::Result;
+
+mod memory;
+mod sqlite;
+
+pub(crate) use memory::MemoryUserStore;
+pub(crate) use sqlite::SqliteUserStore;
+
+#[derive(Clone, Debug)]
+pub(crate) struct User {
+    pub(crate) id: Uuid,
+    pub(crate) did: String,
+    pub(crate) keys: Option<DidKeys>,
+    pub(crate) credentials: Vec<Credential>,
+}
+
+/// The derived public keys bound to a DID during registration, as `did:key`s.
+#[derive(Clone, Debug)]
+pub(crate) struct DidKeys {
+    pub(crate) signing_key: String,
+    pub(crate) rotation_key: String,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Credential {
+    pub(crate) passkey: Passkey,
+    pub(crate) derivation: Version,
+    /// The algorithm of the derived keys, known along with the signing key.
+    pub(crate) algorithm: Option<Algorithm>,
+    /// The derived signing key as `did:key`, checked on every login.
+    pub(crate) signing_key: Option<String>,
+    pub(crate) nickname: Option<String>,
+    pub(crate) created_at: u64,
+    pub(crate) last_used_at: Option<u64>,
+}
+
+impl User {
+    pub(crate) fn passkeys(&self) -> Vec<Passkey> {
+        self.credentials
+            .iter()
+            .map(|credential| credential.passkey.clone())
+            .collect()
+    }
+
+    pub(crate) fn credential(&self, credential_id: &[u8]) -> Option<&Credential> {
+        self.credentials
+            .iter()
+            .find(|credential| credential.id() == credential_id)
+    }
+}
+
+impl Credential {
+    pub(crate) fn new(
+        passkey: Passkey,
+        derivation: Version,
+        algorithm: Algorithm,
+        signing_key: String,
+    ) -> Self {
+        Self {
+            passkey,
+            derivation,
+            algorithm: Some(algorithm),
+            signing_key: Some(signing_key),
+            nickname: None,
+            created_at: now(),
+            last_used_at: None,
+        }
+    }
+
+    pub(crate) fn id(&self) -> &[u8] {
+        self.passkey.cred_id()
+    }
+
+    pub(crate) fn backup_state(&self) -> bool {
+        webauthn_rs::prelude::Credential::from(self.passkey.clone()).backup_state
+    }
+}
+
+/// Seconds since the unix epoch, as stored in credential timestamps.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+pub(crate) trait UserStore: Send + Sync {
+    fn get_by_did(&self, did: &str) -> Result<Option<User>>;
+    fn get_by_id(&self, id: Uuid) -> Result<Option<User>>;
+
+    /// Inserts a registered user with its credentials and keys at once,
+    /// failing if the DID or a credential is already taken.
+    fn insert(&self, user: User) -> Result<()>;
+
+    /// Adds a credential to the user, failing if it is already registered to
+    /// any user.
+    fn attach_credential(&self, id: Uuid, credential: Credential) -> Result<()>;
+
+    /// Records a login with the credential, updating its counter and backup
+    /// state from the authentication along with when it was last used.
+    fn record_use(&self, id: Uuid, auth: &AuthenticationResult) -> Result<()>;
+
+    /// Records the algorithm of the credential's derived keys, binding the
+    /// signing key unless one is bound already.
+    fn bind_signing_key(
+        &self,
+        id: Uuid,
+        credential_id: &[u8],
+        algorithm: Algorithm,
+        signing_key: &str,
+    ) -> Result<()>;
+
+    /// Sets or, with `None`, clears the credential's nickname.
+    fn set_nickname(&self, id: Uuid, credential_id: &[u8], nickname: Option<&str>) -> Result<()>;
+
+    /// Removes a credential, refusing to remove the user's last one.
+    fn remove_credential(&self, id: Uuid, credential_id: &[u8]) -> Result<()>;
+
+    /// Removes the user along with its credentials.
+    fn delete(&self, id: Uuid) -> Result<()>;
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Storage {
+    Memory,
+    Sqlite(PathBuf),
+}
+
+impl Storage {
+    pub(crate) fn open(&self) -> Result<Arc<dyn UserStore>> {
+        let store: Arc<dyn UserStore> = match self {
+            Self::Memory => Arc::new(MemoryUserStore::default()),
+            Self::Sqlite(path) => Arc::new(SqliteUserStore::open(path)?),
+        };
+
+        Ok(store)
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use api::ErrorCode;
+use keygen::Algorithm;
+use uuid::Uuid;
+use webauthn_rs::prelude::AuthenticationResult;
+
+use crate::error::{Error, ErrorCodeExt, Result};
+use crate::store::{self, Credential, User, UserStore};
+
+type Did = String;
+
+#[derive(Default)]
+pub(crate) struct MemoryUserStore {
+    users: Mutex<HashMap<Did, User>>,
+}
+
+impl MemoryUserStore {
+    fn credential_mut<'a>(
+        users: &'a mut HashMap<Did, User>,
+        id: Uuid,
+        credential_id: &[u8],
+    ) -> Result<&'a mut Credential> {
+        users
+            .values_mut()
+            .find(|user| user.id == id)
+            .or_code(
+                ErrorCode::UserNotFound,
+                format!("no user found for uuid {id}"),
+            )?
+            .credentials
+            .iter_mut()
+            .find(|existing| existing.id() == credential_id)
+            .or_code(ErrorCode::CredentialNotFound, "no such credential")
+    }
+}
+
+impl UserStore for MemoryUserStore {
+    fn get_by_did(&self, did: &str) -> Result<Option<User>> {
+        let users = self.users.lock().map_err(Error::from_poison)?;
+        Ok(users.get(did).cloned())
+    }
+
+    fn get_by_id(&self, id: Uuid) -> Result<Option<User>> {
+        let users = self.users.lock().map_err(Error::from_poison)?;
+        Ok(users.values().find(|user| user.id == id).cloned())
+    }
+
+    fn insert(&self, user: User) -> Result<()> {
+        let mut users = self.users.lock().map_err(Error::from_poison)?;
+
+        if users.contains_key(&user.did) {
+            return Err(Error::with_code(
+                ErrorCode::UserExists,
+                "user already exists",
+            ));
+        }
+
+        let registered = users
+            .values()
+            .flat_map(|existing| &existing.credentials)
+            .any(|existing| user.credential(existing.id()).is_some());
+
+        if registered {
+            return Err(Error::with_code(
+                ErrorCode::CredentialExists,
+                "credential already registered",
+            ));
+        }
+
+        users.insert(user.did.clone(), user);
+
+        Ok(())
+    }
+
+    fn attach_credential(&self, id: Uuid, credential: Credential) -> Result<()> {
+        let mut users = self.users.lock().map_err(Error::from_poison)?;
+
+        let registered = users
+            .values()
+            .flat_map(|user| &user.credentials)
+            .any(|existing| existing.id() == credential.id());
+
+        if registered {
+            return Err(Error::with_code(
+                ErrorCode::CredentialExists,
+                "credential already registered",
+            ));
+        }
+
+        users
+            .values_mut()
+            .find(|user| user.id == id)
+            .or_code(
+                ErrorCode::UserNotFound,
+                format!("no user found for uuid {id}"),
+            )?
+            .credentials
+            .push(credential);
+
+        Ok(())
+    }
+
+    fn record_use(&self, id: Uuid, auth: &AuthenticationResult) -> Result<()> {
+        let mut users = self.users.lock().map_err(Error::from_poison)?;
+
+        let credential = Self::credential_mut(&mut users, id, auth.cred_id())?;
+        credential.passkey.update_credential(auth);
+        credential.last_used_at = Some(store::now());
+
+        Ok(())
+    }
+
+    fn bind_signing_key(
+        &self,
+        id: Uuid,
+        credential_id: &[u8],
+        algorithm: Algorithm,
+        signing_key: &str,
+    ) -> Result<()> {
+        let mut users = self.users.lock().map_err(Error::from_poison)?;
+
+        let credential = Self::credential_mut(&mut users, id, credential_id)?;
+        credential.algorithm = Some(algorithm);
+        credential
+            .signing_key
+            .get_or_insert_with(|| signing_key.to_owned());
+
+        Ok(())
+    }
+
+    fn set_nickname(&self, id: Uuid, credential_id: &[u8], nickname: Option<&str>) -> Result<()> {
+        let mut users = self.users.lock().map_err(Error::from_poison)?;
+
+        Self::credential_mut(&mut users, id, credential_id)?.nickname = nickname.map(str::to_owned);
+
+        Ok(())
+    }
+
+    fn remove_credential(&self, id: Uuid, credential_id: &[u8]) -> Result<()> {
+        let mut users = self.users.lock().map_err(Error::from_poison)?;
+
+        let credentials = &mut users
+            .values_mut()
+            .find(|user| user.id == id)
+            .or_code(
+                ErrorCode::UserNotFound,
+                format!("no user found for uuid {id}"),
+            )?
+            .credentials;
+
+        let index = credentials
+            .iter()
+            .position(|credential| credential.id() == credential_id)
+            .or_code(ErrorCode::CredentialNotFound, "no such credential")?;
+
+        if credentials.len() == 1 {
+            return Err(Error::with_code(
+                ErrorCode::LastCredential,
+                "cannot remove the last credential",
+            ));
+        }
+
+        credentials.remove(index);
+
+        Ok(())
+    }
+
+    fn delete(&self, id: Uuid) -> Result<()> {
+        let mut users = self.users.lock().map_err(Error::from_poison)?;
+        let count = users.len();
+
+        users.retain(|_, user| user.id != id);
+
+        if users.len() == count {
+            return Err(Error::with_code(ErrorCode::UserNotFound, "no such user"));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use api::ErrorCode;
+use keygen::{Algorithm, Version};
+use rusqlite::{Connection, OptionalExtension, params};
+use uuid::Uuid;
+use webauthn_rs::prelude::{AuthenticationResult, Passkey};
+
+use crate::error::{Context, Error, ErrorCodeExt, Result};
+use crate::store::{self, Credential, DidKeys, User, UserStore};
+
+const SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+
+    CREATE TABLE IF NOT EXISTS users (
+        id TEXT PRIMARY KEY NOT NULL,
+        did TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE IF NOT EXISTS passkeys (
+        credential_id BLOB PRIMARY KEY NOT NULL,
+        user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        passkey TEXT NOT NULL,
+        nickname TEXT,
+        created_at INTEGER NOT NULL,
+        last_used_at INTEGER
+    );
+";
+
+/// Applied in order on top of [`SCHEMA`], tracked in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    // Credentials registered before versioned key derivation use v1
+    "ALTER TABLE passkeys ADD COLUMN derivation INTEGER NOT NULL DEFAULT 1;",
+    "ALTER TABLE users ADD COLUMN signing_key TEXT;
+     ALTER TABLE users ADD COLUMN rotation_key TEXT;",
+    "ALTER TABLE passkeys ADD COLUMN signing_key TEXT;",
+    // The multibase prefixes of compressed did:keys tell the algorithm apart
+    "ALTER TABLE passkeys ADD COLUMN algorithm TEXT;
+     UPDATE passkeys SET algorithm = CASE
+         WHEN signing_key LIKE 'did:key:zDn%' THEN 'p256'
+         WHEN signing_key LIKE 'did:key:zQ3s%' THEN 'secp256k1'
+         WHEN signing_key LIKE 'did:key:z6Mk%' THEN 'ed25519'
+     END;",
+];
+
+pub(crate) struct SqliteUserStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteUserStore {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let mut conn = Connection::open(path)
+            .context(format!("failed to open database {}", path.display()))?;
+
+        conn.execute_batch(SCHEMA)
+            .context("failed to initialize database schema")?;
+
+        Self::migrate(&mut conn)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn migrate(conn: &mut Connection) -> Result<()> {
+        let tx = conn.transaction().context("failed to start transaction")?;
+
+        let version: usize = tx
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .context("failed to read schema version")?;
+
+        // Migrating would mark it as the older version it doesn't match
+        if version > MIGRATIONS.len() {
+            return Err(anyhow::anyhow!(
+                "database schema version {version} is newer than the supported {}",
+                MIGRATIONS.len()
+            )
+            .into());
+        }
+
+        for migration in MIGRATIONS.iter().skip(version) {
+            tx.execute_batch(migration)
+                .context("failed to migrate database schema")?;
+        }
+
+        tx.pragma_update(None, "user_version", MIGRATIONS.len())
+            .context("failed to update schema version")?;
+
+        tx.commit().context("failed to commit transaction")?;
+
+        Ok(())
+    }
+
+    fn query_user(&self, sql: &str, param: &str) -> Result<Option<User>> {
+        let conn = self.conn.lock().map_err(Error::from_poison)?;
+
+        let row = conn
+            .query_row(sql, [param], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })
+            .optional()
+            .context("failed to query user")?;
+
+        let Some((id, did, signing_key, rotation_key)) = row else {
+            return Ok(None);
+        };
+
+        let keys = signing_key
+            .zip(rotation_key)
+            .map(|(signing_key, rotation_key)| DidKeys {
+                signing_key,
+                rotation_key,
+            });
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT passkey, derivation, algorithm, signing_key, nickname, created_at,
+                 last_used_at FROM passkeys WHERE user_id = ?1 ORDER BY rowid",
+            )
+            .context("failed to prepare passkey query")?;
+
+        let credentials = stmt
+            .query_map([&id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u8>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                ))
+            })
+            .context("failed to query passkeys")?
+            .map(|row| {
+                let (
+                    passkey,
+                    derivation,
+                    algorithm,
+                    signing_key,
+                    nickname,
+                    created_at,
+                    last_used_at,
+                ) = row.context("failed to read passkey")?;
+
+                let passkey =
+                    serde_json::from_str(&passkey).context("malformed passkey in database")?;
+                let derivation = Version::try_from(derivation)?;
+                let algorithm = algorithm
+                    .map(|algorithm| algorithm.parse::<Algorithm>())
+                    .transpose()?;
+
+                Ok(Credential {
+                    passkey,
+                    derivation,
+                    algorithm,
+                    signing_key,
+                    nickname,
+                    created_at,
+                    last_used_at,
+                })
+            })
+            .collect::<anyhow::Result<Vec<Credential>>>()?;
+
+        let id = Uuid::parse_str(&id).context("malformed user id in database")?;
+
+        Ok(Some(User {
+            id,
+            did,
+            keys,
+            credentials,
+        }))
+    }
+
+    /// Fails unless an update matched the credential.
+    fn updated(rows: usize) -> Result<()> {
+        if rows == 0 {
+            Err(Error::with_code(
+                ErrorCode::CredentialNotFound,
+                "no such credential",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Inserts a passkey of the user, which must exist.
+    fn insert_passkey(conn: &Connection, id: Uuid, credential: &Credential) -> Result<()> {
+        let passkey =
+            serde_json::to_string(&credential.passkey).context("failed to serialize passkey")?;
+
+        let inserted = conn
+            .execute(
+                "INSERT OR IGNORE INTO passkeys
+                 (credential_id, user_id, passkey, derivation, algorithm, signing_key, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    credential.id(),
+                    id.to_string(),
+                    passkey,
+                    credential.derivation.as_u8(),
+                    credential.algorithm.map(|algorithm| algorithm.as_str()),
+                    credential.signing_key,
+                    credential.created_at
+                ],
+            )
+            .context("failed to attach passkey")?;
+
+        if inserted == 0 {
+            Err(Error::with_code(
+                ErrorCode::CredentialExists,
+                "credential already registered",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl UserStore for SqliteUserStore {
+    fn get_by_did(&self, did: &str) -> Result<Option<User>> {
+        self.query_user(
+            "SELECT id, did, signing_key, rotation_key FROM users WHERE did = ?1",
+            did,
+        )
+    }
+
+    fn get_by_id(&self, id: Uuid) -> Result<Option<User>> {
+        self.query_user(
+            "SELECT id, did, signing_key, rotation_key FROM users WHERE id = ?1",
+            &id.to_string(),
+        )
+    }
+
+    fn insert(&self, user: User) -> Result<()> {
+        let mut conn = self.conn.lock().map_err(Error::from_poison)?;
+        let tx = conn.transaction().context("failed to start transaction")?;
+
+        let (signing_key, rotation_key) = user
+            .keys
+            .map(|keys| (keys.signing_key, keys.rotation_key))
+            .unzip();
+
+        let inserted = tx
+            .execute(
+                "INSERT OR IGNORE INTO users (id, did, signing_key, rotation_key)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![user.id.to_string(), user.did, signing_key, rotation_key],
+            )
+            .context("failed to insert user")?;
+
+        if inserted == 0 {
+            return Err(Error::with_code(
+                ErrorCode::UserExists,
+                "user already exists",
+            ));
+        }
+
+        for credential in &user.credentials {
+            Self::insert_passkey(&tx, user.id, credential)?;
+        }
+
+        tx.commit().context("failed to commit transaction")?;
+
+        Ok(())
+    }
+
+    fn attach_credential(&self, id: Uuid, credential: Credential) -> Result<()> {
+        let conn = self.conn.lock().map_err(Error::from_poison)?;
+
+        let exists = conn
+            .query_row(
+                "SELECT 1 FROM users WHERE id = ?1",
+                [id.to_string()],
+                |_| Ok(()),
+            )
+            .optional()
+            .context("failed to query user")?;
+
+        if exists.is_none() {
+            return Err(Error::with_code(ErrorCode::UserNotFound, "no such user"));
+        }
+
+        Self::insert_passkey(&conn, id, &credential)
+    }
+
+    fn record_use(&self, id: Uuid, auth: &AuthenticationResult) -> Result<()> {
+        let mut conn = self.conn.lock().map_err(Error::from_poison)?;
+        let tx = conn.transaction().context("failed to start transaction")?;
+
+        let passkey: Option<String> = tx
+            .query_row(
+                "SELECT passkey FROM passkeys WHERE credential_id = ?1 AND user_id = ?2",
+                params![auth.cred_id().as_slice(), id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("failed to query passkey")?;
+
+        let mut passkey: Passkey = serde_json::from_str(
+            &passkey.or_code(ErrorCode::CredentialNotFound, "no such credential")?,
+        )
+        .context("malformed passkey in database")?;
+
+        passkey.update_credential(auth);
+
+        let passkey = serde_json::to_string(&passkey).context("failed to serialize passkey")?;
+
+        tx.execute(
+            "UPDATE passkeys SET passkey = ?1, last_used_at = ?2
+             WHERE credential_id = ?3 AND user_id = ?4",
+            params![
+                passkey,
+                store::now(),
+                auth.cred_id().as_slice(),
+                id.to_string()
+            ],
+        )
+        .context("failed to record credential use")?;
+
+        tx.commit().context("failed to commit transaction")?;
+
+        Ok(())
+    }
+
+    fn bind_signing_key(
+        &self,
+        id: Uuid,
+        credential_id: &[u8],
+        algorithm: Algorithm,
+        signing_key: &str,
+    ) -> Result<()> {
+        let conn = self.conn.lock().map_err(Error::from_poison)?;
+
+        let updated = conn
+            .execute(
+                "UPDATE passkeys SET algorithm = ?1, signing_key = COALESCE(signing_key, ?2)
+                 WHERE credential_id = ?3 AND user_id = ?4",
+                params![
+                    algorithm.as_str(),
+                    signing_key,
+                    credential_id,
+                    id.to_string()
+                ],
+            )
+            .context("failed to bind signing key")?;
+
+        Self::updated(updated)
+    }
+
+    fn set_nickname(&self, id: Uuid, credential_id: &[u8], nickname: Option<&str>) -> Result<()> {
+        let conn = self.conn.lock().map_err(Error::from_poison)?;
+
+        let updated = conn
+            .execute(
+                "UPDATE passkeys SET nickname = ?1 WHERE credential_id = ?2 AND user_id = ?3",
+                params![nickname, credential_id, id.to_string()],
+            )
+            .context("failed to update nickname")?;
+
+        Self::updated(updated)
+    }
+
+    fn remove_credential(&self, id: Uuid, credential_id: &[u8]) -> Result<()> {
+        let mut conn = self.conn.lock().map_err(Error::from_poison)?;
+        let tx = conn.transaction().context("failed to start transaction")?;
+
+        let count: u64 = tx
+            .query_row(
+                "SELECT COUNT(*) FROM passkeys WHERE user_id = ?1",
+                [id.to_string()],
+                |row| row.get(0),
+            )
+            .context("failed to count credentials")?;
+
+        let removed = tx
+            .execute(
+                "DELETE FROM passkeys WHERE credential_id = ?1 AND user_id = ?2",
+                params![credential_id, id.to_string()],
+            )
+            .context("failed to remove credential")?;
+
+        if removed == 0 {
+            return Err(Error::with_code(
+                ErrorCode::CredentialNotFound,
+                "no such credential",
+            ));
+        }
+
+        if count <= 1 {
+            return Err(Error::with_code(
+                ErrorCode::LastCredential,
+                "cannot remove the last credential",
+            ));
+        }
+
+        tx.commit().context("failed to commit transaction")?;
+
+        Ok(())
+    }
+
+    fn delete(&self, id: Uuid) -> Result<()> {
+        let conn = self.conn.lock().map_err(Error::from_poison)?;
+
+        // The user's passkeys go with it through ON DELETE CASCADE
+        let deleted = conn
+            .execute("DELETE FROM users WHERE id = ?1", [id.to_string()])
+            .context("failed to delete user")?;
+
+        if deleted == 0 {
+            return Err(Error::with_code(ErrorCode::UserNotFound, "no such user"));
+        }
+
+        Ok(())
+    }
+}
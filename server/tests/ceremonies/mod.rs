@@ -0,0 +1,1090 @@
+//! Ceremony tests shared by the user store backends, which tell whether to
+//! keep users in a SQLite database as `SQLITE`.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use api::*;
+use authenticator::SoftPasskey;
+use axum::Router;
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode, header};
+use http_body_util::BodyExt;
+use keygen::{Algorithm, Keygen, Keypair, Keys, Version, didkey, proof};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use server::config::Session;
+use server::{App, Config};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+pub(crate) const DID: &str = "did:plc:ewvi7nxzyoun6zhxrhs64oiz";
+
+/// Starts usernameless logins for the DID input's autofill or from a button.
+const AUTOFILL: StartDiscoverableAuthenticationRequest =
+    StartDiscoverableAuthenticationRequest { conditional: true };
+const BUTTON: StartDiscoverableAuthenticationRequest =
+    StartDiscoverableAuthenticationRequest { conditional: false };
+
+/// Drives the router like a browser session, keeping the session cookie.
+pub(crate) struct Client {
+    router: Router,
+    cookie: Option<String>,
+}
+
+pub(crate) struct Response {
+    status: StatusCode,
+    body: Vec<u8>,
+}
+
+impl Client {
+    pub(crate) fn new(router: &Router) -> Self {
+        Self {
+            router: router.clone(),
+            cookie: None,
+        }
+    }
+
+    pub(crate) async fn get(&mut self, path: &str) -> Response {
+        self.send(Method::GET, path, Body::empty()).await
+    }
+
+    pub(crate) async fn post<T: Serialize>(&mut self, path: &str, body: &T) -> Response {
+        let body = serde_json::to_vec(body).unwrap();
+        self.send(Method::POST, path, Body::from(body)).await
+    }
+
+    async fn send(&mut self, method: Method, path: &str, body: Body) -> Response {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(header::CONTENT_TYPE, "application/json");
+
+        if let Some(cookie) = &self.cookie {
+            req = req.header(header::COOKIE, cookie);
+        }
+
+        let res = self
+            .router
+            .clone()
+            .oneshot(req.body(body).unwrap())
+            .await
+            .unwrap();
+
+        if let Some(cookie) = res.headers().get(header::SET_COOKIE) {
+            let cookie = cookie.to_str().unwrap();
+            let (cookie, _) = cookie.split_once(';').unwrap_or((cookie, ""));
+            self.cookie = Some(cookie.to_owned());
+        }
+
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes().to_vec();
+
+        Response { status, body }
+    }
+
+    /// Registers `did` with a new credential of the passkey, returning the
+    /// derived keys.
+    pub(crate) async fn register(&mut self, passkey: &mut SoftPasskey, did: &str) -> Keys {
+        let res: StartRegistrationResponse = self
+            .post(
+                "/register/start",
+                &StartRegistrationRequest {
+                    did: did.to_owned(),
+                },
+            )
+            .await
+            .json();
+
+        let registration = passkey.create(&res.challenge, b"").unwrap();
+        let keys = derive(&registration.prf, did, Algorithm::P256);
+        let challenge = &res.challenge.public_key.challenge;
+
+        let res: FinishRegistrationResponse = self
+            .post(
+                "/register/finish",
+                &FinishRegistrationRequest {
+                    credential: registration.credential,
+                    derivation: Version::LATEST,
+                    signing_key: key_proof(&keys.signing, did, challenge),
+                    rotation_key: key_proof(&keys.rotation, did, challenge),
+                },
+            )
+            .await
+            .json();
+
+        assert!(res.success);
+
+        keys
+    }
+
+    /// Starts a login and answers the challenge with the passkey, deriving the
+    /// keys with the credential's recorded derivation version.
+    pub(crate) async fn start_login(
+        &mut self,
+        passkey: &mut SoftPasskey,
+        did: &str,
+        algorithm: Algorithm,
+    ) -> FinishAuthenticationRequest {
+        let res: StartAuthenticationResponse = self
+            .post(
+                "/auth/start",
+                &StartAuthenticationRequest {
+                    did: did.to_owned(),
+                },
+            )
+            .await
+            .json();
+
+        let assertion = passkey.get(&res.challenge, b"").unwrap();
+        let derivation = res
+            .derivations
+            .iter()
+            .find(|derivation| derivation.credential_id == assertion.credential.raw_id)
+            .unwrap()
+            .derivation;
+        let keys = Keygen::new(derivation)
+            .generate_keys(&assertion.prf, did.as_bytes(), algorithm)
+            .unwrap();
+
+        FinishAuthenticationRequest {
+            credential: assertion.credential,
+            signing_key: key_proof(&keys.signing, did, &res.challenge.public_key.challenge),
+        }
+    }
+
+    /// Runs a usernameless login up to the identified user, returning the key
+    /// proof the client would send for it.
+    async fn identify(
+        &mut self,
+        passkey: &mut SoftPasskey,
+        algorithm: Algorithm,
+    ) -> (
+        FinishDiscoverableAuthenticationResponse,
+        VerifyDiscoverableAuthenticationRequest,
+    ) {
+        let res: StartDiscoverableAuthenticationResponse =
+            self.post("/auth/discover/start", &BUTTON).await.json();
+        assert!(res.challenge.public_key.allow_credentials.is_empty());
+
+        let challenge = res.challenge.public_key.challenge.clone();
+        let assertion = passkey.get(&res.challenge, b"").unwrap();
+
+        let res: FinishDiscoverableAuthenticationResponse = self
+            .post(
+                "/auth/discover/finish",
+                &FinishDiscoverableAuthenticationRequest {
+                    credential: assertion.credential,
+                },
+            )
+            .await
+            .json();
+
+        // The DID from the server derives the keys
+        let keys = Keygen::new(res.derivation)
+            .generate_keys(&assertion.prf, res.did.as_bytes(), algorithm)
+            .unwrap();
+        let req = VerifyDiscoverableAuthenticationRequest {
+            signing_key: key_proof(&keys.signing, &res.did, &challenge),
+        };
+
+        (res, req)
+    }
+}
+
+impl Response {
+    pub(crate) fn json<T: DeserializeOwned>(self) -> T {
+        assert_eq!(
+            self.status,
+            StatusCode::OK,
+            "{}",
+            String::from_utf8_lossy(&self.body)
+        );
+
+        serde_json::from_slice(&self.body).unwrap()
+    }
+
+    fn error(self, status: StatusCode, code: ErrorCode) {
+        let err: ErrorResponse = serde_json::from_slice(&self.body).unwrap();
+
+        assert_eq!((self.status, err.code), (status, code), "{}", err.error);
+    }
+}
+
+/// A database file in the temp dir, deleted when dropped.
+pub(crate) struct TempDatabase(PathBuf);
+
+impl TempDatabase {
+    /// Names a new file, which SQLite creates when it is first opened.
+    pub(crate) fn new() -> Self {
+        Self(std::env::temp_dir().join(format!("server-test-{}.sqlite", Uuid::new_v4())))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        // Never opened if the test failed before starting the server
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// The router under test, keeping its database until dropped.
+struct TestRouter {
+    router: Router,
+    _database: Option<TempDatabase>,
+}
+
+impl Deref for TestRouter {
+    type Target = Router;
+
+    fn deref(&self) -> &Router {
+        &self.router
+    }
+}
+
+fn router() -> TestRouter {
+    router_with(Session::default())
+}
+
+fn router_with(session: Session) -> TestRouter {
+    let database = super::SQLITE.then(TempDatabase::new);
+    let config = Config {
+        session,
+        database: database.as_ref().map(|database| database.path().to_owned()),
+        ..Config::default()
+    };
+
+    TestRouter {
+        router: App::new(config).into_router().unwrap(),
+        _database: database,
+    }
+}
+
+pub(crate) fn passkey() -> SoftPasskey {
+    SoftPasskey::new(Config::default().rp.origin)
+}
+
+fn derive(prf: &[u8], did: &str, algorithm: Algorithm) -> Keys {
+    Keygen::new(Version::LATEST)
+        .generate_keys(prf, did.as_bytes(), algorithm)
+        .unwrap()
+}
+
+fn key_proof(keypair: &Keypair, did: &str, challenge: &[u8]) -> KeyProof {
+    KeyProof {
+        did_key: didkey::encode(keypair.algorithm(), &keypair.public_key()).unwrap(),
+        signature: proof::sign(keypair, did, challenge).unwrap().into(),
+    }
+}
+
+#[tokio::test]
+async fn registers_and_logs_in() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    let keys = client.register(&mut passkey, DID).await;
+
+    // Registering alone doesn't log in
+    client
+        .get("/auth/me")
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated);
+
+    // The key type is recorded along with the derivation
+    let res: StartAuthenticationResponse = client
+        .post(
+            "/auth/start",
+            &StartAuthenticationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .json();
+    let derivation = &res.derivations[0];
+    assert_eq!(
+        (derivation.derivation, derivation.algorithm),
+        (Version::LATEST, Some(Algorithm::P256))
+    );
+
+    let req = client.start_login(&mut passkey, DID, Algorithm::P256).await;
+    let res: FinishAuthenticationResponse = client.post("/auth/finish", &req).await.json();
+
+    let me: MeResponse = client.get("/auth/me").await.json();
+    assert_eq!((me.user_id, me.did.as_str()), (res.user_id, DID));
+
+    let bound: LookupKeysResponse = client.get(&format!("/keys/{DID}")).await.json();
+    let signing_key = didkey::encode(Algorithm::P256, &keys.signing.public_key()).unwrap();
+    let rotation_key = didkey::encode(Algorithm::P256, &keys.rotation.public_key()).unwrap();
+    assert_eq!(
+        (bound.signing_keys, bound.rotation_key),
+        (vec![signing_key], Some(rotation_key))
+    );
+
+    let res: LogoutResponse = client.post("/auth/logout", &()).await.json();
+    assert_eq!(res.user_id, Some(me.user_id));
+
+    client
+        .get("/auth/me")
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated);
+}
+
+#[tokio::test]
+async fn logout_does_not_reveal_a_started_logins_user() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    client.register(&mut passkey, DID).await;
+
+    let _: StartAuthenticationResponse = client
+        .post(
+            "/auth/start",
+            &StartAuthenticationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .json();
+
+    let res: LogoutResponse = client.post("/auth/logout", &()).await.json();
+    assert_eq!(res.user_id, None);
+}
+
+#[tokio::test]
+async fn cycles_the_session_id_on_login() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    client.register(&mut passkey, DID).await;
+
+    let req = client.start_login(&mut passkey, DID, Algorithm::P256).await;
+
+    // A session id planted before the login stays logged out
+    let mut planted = Client::new(&router);
+    planted.cookie = client.cookie.clone();
+
+    let _: FinishAuthenticationResponse = client.post("/auth/finish", &req).await.json();
+    assert_ne!(client.cookie, planted.cookie);
+
+    let _: MeResponse = client.get("/auth/me").await.json();
+    planted
+        .get("/auth/me")
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated);
+}
+
+#[tokio::test]
+async fn logs_in_with_discoverable_credentials() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    let keys = client.register(&mut passkey, DID).await;
+
+    // Autofill is re-armed before its challenge expires
+    let res: StartDiscoverableAuthenticationResponse =
+        client.post("/auth/discover/start", &AUTOFILL).await.json();
+    assert_eq!(res.expires_in_secs, Session::default().ceremony_expiry_secs);
+
+    let (res, req) = client.identify(&mut passkey, Algorithm::P256).await;
+    assert_eq!(
+        (res.did.as_str(), res.derivation, res.algorithm),
+        (DID, Version::LATEST, Some(Algorithm::P256))
+    );
+
+    let signing_key = didkey::encode(Algorithm::P256, &keys.signing.public_key()).unwrap();
+    assert_eq!(req.signing_key.did_key, signing_key);
+
+    // Not logged in before the key is verified
+    client
+        .get("/auth/me")
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated);
+
+    let verified: VerifyDiscoverableAuthenticationResponse =
+        client.post("/auth/discover/verify", &req).await.json();
+    assert_eq!(verified.user_id, res.user_id);
+
+    let me: MeResponse = client.get("/auth/me").await.json();
+    assert_eq!((me.user_id, me.did.as_str()), (res.user_id, DID));
+
+    // Autofill on the next page load leaves the session logged in
+    client
+        .post("/auth/discover/start", &AUTOFILL)
+        .await
+        .error(StatusCode::CONFLICT, ErrorCode::AlreadyAuthenticated);
+
+    let _: MeResponse = client.get("/auth/me").await.json();
+
+    // The login button still works with an empty DID input after a reload
+    let (res, req) = client.identify(&mut passkey, Algorithm::P256).await;
+    let verified: VerifyDiscoverableAuthenticationResponse =
+        client.post("/auth/discover/verify", &req).await.json();
+    assert_eq!(verified.user_id, res.user_id);
+
+    let me: MeResponse = client.get("/auth/me").await.json();
+    assert_eq!(me.did, DID);
+}
+
+#[tokio::test]
+async fn manages_credentials() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    client.register(&mut passkey, DID).await;
+
+    // Managing credentials requires a login
+    client
+        .get("/credentials")
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated);
+
+    let req = client.start_login(&mut passkey, DID, Algorithm::P256).await;
+    let _: FinishAuthenticationResponse = client.post("/auth/finish", &req).await.json();
+
+    let res: ListCredentialsResponse = client.get("/credentials").await.json();
+    let [credential] = res.credentials.as_slice() else {
+        panic!("expected one credential, got {:?}", res.credentials);
+    };
+
+    assert_eq!(credential.credential_id, req.credential.raw_id);
+    assert_eq!(credential.derivation, Version::LATEST);
+    assert_eq!(credential.nickname, None);
+    assert!(credential.last_used_at.is_some());
+
+    let credential_id = credential.credential_id.clone();
+
+    let res: RenameCredentialResponse = client
+        .post(
+            "/credentials/rename",
+            &RenameCredentialRequest {
+                credential_id: credential_id.clone(),
+                nickname: "  Laptop ".to_owned(),
+            },
+        )
+        .await
+        .json();
+    assert_eq!(res.credential.nickname.as_deref(), Some("Laptop"));
+
+    let res: ListCredentialsResponse = client.get("/credentials").await.json();
+    assert_eq!(res.credentials[0].nickname.as_deref(), Some("Laptop"));
+
+    // A blank nickname clears it
+    let res: RenameCredentialResponse = client
+        .post(
+            "/credentials/rename",
+            &RenameCredentialRequest {
+                credential_id: credential_id.clone(),
+                nickname: " ".to_owned(),
+            },
+        )
+        .await
+        .json();
+    assert_eq!(res.credential.nickname, None);
+
+    client
+        .post(
+            "/credentials/rename",
+            &RenameCredentialRequest {
+                credential_id: vec![1, 2, 3].into(),
+                nickname: "Phone".to_owned(),
+            },
+        )
+        .await
+        .error(StatusCode::NOT_FOUND, ErrorCode::CredentialNotFound);
+
+    client
+        .post(
+            "/credentials/revoke",
+            &RevokeCredentialRequest {
+                credential_id: vec![1, 2, 3].into(),
+            },
+        )
+        .await
+        .error(StatusCode::NOT_FOUND, ErrorCode::CredentialNotFound);
+
+    // The last credential can't be revoked
+    client
+        .post(
+            "/credentials/revoke",
+            &RevokeCredentialRequest { credential_id },
+        )
+        .await
+        .error(StatusCode::CONFLICT, ErrorCode::LastCredential);
+
+    let res: ListCredentialsResponse = client.get("/credentials").await.json();
+    assert_eq!(res.credentials.len(), 1);
+}
+
+#[tokio::test]
+async fn adds_credentials() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+    let mut device = SoftPasskey::new(Config::default().rp.origin);
+
+    let keys = client.register(&mut passkey, DID).await;
+
+    client
+        .post("/credentials/add/start", &())
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated);
+
+    let req = client.start_login(&mut passkey, DID, Algorithm::P256).await;
+    let _: FinishAuthenticationResponse = client.post("/auth/finish", &req).await.json();
+    let first_id = req.credential.raw_id;
+
+    let res: StartAddCredentialResponse = client.post("/credentials/add/start", &()).await.json();
+
+    // The authenticator is told about the existing credentials
+    let excluded = res
+        .challenge
+        .public_key
+        .exclude_credentials
+        .as_ref()
+        .unwrap();
+    assert_eq!(excluded.len(), 1);
+    assert_eq!(excluded[0].id, first_id);
+
+    // Every authenticator has its own PRF and hence its own keys
+    let registration = device.create(&res.challenge, b"").unwrap();
+    let device_keys = derive(&registration.prf, DID, Algorithm::P256);
+    assert_ne!(device_keys.signing.public_key(), keys.signing.public_key());
+
+    let res: FinishAddCredentialResponse = client
+        .post(
+            "/credentials/add/finish",
+            &FinishAddCredentialRequest {
+                credential: registration.credential,
+                derivation: Version::LATEST,
+                signing_key: key_proof(
+                    &device_keys.signing,
+                    DID,
+                    &res.challenge.public_key.challenge,
+                ),
+            },
+        )
+        .await
+        .json();
+    let device_id = res.credential_id;
+
+    // Adding a credential keeps the session logged in
+    let list: ListCredentialsResponse = client.get("/credentials").await.json();
+    let ids: Vec<_> = list.credentials.iter().map(|c| &c.credential_id).collect();
+    assert_eq!(ids, [&first_id, &device_id]);
+
+    // The new device logs in with its own key
+    let mut other = Client::new(&router);
+    let req = other.start_login(&mut device, DID, Algorithm::P256).await;
+    assert_eq!(req.credential.raw_id, device_id);
+    let _: FinishAuthenticationResponse = other.post("/auth/finish", &req).await.json();
+
+    let signing_key = didkey::encode(Algorithm::P256, &keys.signing.public_key()).unwrap();
+    let rotation_key = didkey::encode(Algorithm::P256, &keys.rotation.public_key()).unwrap();
+    let device_key = didkey::encode(Algorithm::P256, &device_keys.signing.public_key()).unwrap();
+
+    let bound: LookupKeysResponse = other.get(&format!("/keys/{DID}")).await.json();
+    assert_eq!(bound.signing_keys, [signing_key, device_key.clone()]);
+    assert_eq!(bound.rotation_key, Some(rotation_key));
+
+    let res: RevokeCredentialResponse = other
+        .post(
+            "/credentials/revoke",
+            &RevokeCredentialRequest {
+                credential_id: first_id.clone(),
+            },
+        )
+        .await
+        .json();
+    let ids: Vec<_> = res.credentials.iter().map(|c| &c.credential_id).collect();
+    assert_eq!(ids, [&device_id]);
+
+    // The revoked credential's keys are no longer published, and the rotation
+    // key went with the credential that registered the DID
+    let bound: LookupKeysResponse = other.get(&format!("/keys/{DID}")).await.json();
+    assert_eq!(bound.signing_keys, [device_key]);
+    assert_eq!(bound.rotation_key, None);
+
+    // The revoked credential is no longer offered for login
+    let res: StartAuthenticationResponse = Client::new(&router)
+        .post(
+            "/auth/start",
+            &StartAuthenticationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .json();
+    assert!(passkey.get(&res.challenge, b"").is_err());
+}
+
+#[tokio::test]
+async fn restarts_abandoned_credential_additions() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+    let mut device = SoftPasskey::new(Config::default().rp.origin);
+
+    client.register(&mut passkey, DID).await;
+
+    let req = client.start_login(&mut passkey, DID, Algorithm::P256).await;
+    let _: FinishAuthenticationResponse = client.post("/auth/finish", &req).await.json();
+
+    // The user cancels the browser's dialog, so the addition never finishes
+    let _: StartAddCredentialResponse = client.post("/credentials/add/start", &()).await.json();
+
+    let me: MeResponse = client.get("/auth/me").await.json();
+    assert_eq!(me.did, DID);
+
+    let res: ListCredentialsResponse = client.get("/credentials").await.json();
+    assert_eq!(res.credentials.len(), 1);
+
+    client
+        .post("/auth/discover/start", &AUTOFILL)
+        .await
+        .error(StatusCode::CONFLICT, ErrorCode::AlreadyAuthenticated);
+
+    let res: StartAddCredentialResponse = client.post("/credentials/add/start", &()).await.json();
+
+    let registration = device.create(&res.challenge, b"").unwrap();
+    let keys = derive(&registration.prf, DID, Algorithm::P256);
+
+    let _: FinishAddCredentialResponse = client
+        .post(
+            "/credentials/add/finish",
+            &FinishAddCredentialRequest {
+                credential: registration.credential,
+                derivation: Version::LATEST,
+                signing_key: key_proof(&keys.signing, DID, &res.challenge.public_key.challenge),
+            },
+        )
+        .await
+        .json();
+
+    let res: ListCredentialsResponse = client.get("/credentials").await.json();
+    assert_eq!(res.credentials.len(), 2);
+}
+
+#[tokio::test]
+async fn deletes_accounts() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    client.register(&mut passkey, DID).await;
+
+    client
+        .post("/account/delete", &())
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated);
+
+    let req = client.start_login(&mut passkey, DID, Algorithm::P256).await;
+    let res: FinishAuthenticationResponse = client.post("/auth/finish", &req).await.json();
+
+    let deleted: DeleteAccountResponse = client.post("/account/delete", &()).await.json();
+    assert_eq!(deleted.user_id, res.user_id);
+
+    // The session is logged out and the user is gone
+    client
+        .get("/auth/me")
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated);
+
+    client
+        .post(
+            "/auth/start",
+            &StartAuthenticationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .error(StatusCode::NOT_FOUND, ErrorCode::UserNotFound);
+
+    client
+        .get(&format!("/keys/{DID}"))
+        .await
+        .error(StatusCode::NOT_FOUND, ErrorCode::UserNotFound);
+
+    // The deleted credential no longer resolves a user
+    let res: StartDiscoverableAuthenticationResponse =
+        client.post("/auth/discover/start", &BUTTON).await.json();
+    let assertion = passkey.get(&res.challenge, b"").unwrap();
+    assert_eq!(assertion.credential.raw_id, req.credential.raw_id);
+
+    client
+        .post(
+            "/auth/discover/finish",
+            &FinishDiscoverableAuthenticationRequest {
+                credential: assertion.credential,
+            },
+        )
+        .await
+        .error(StatusCode::NOT_FOUND, ErrorCode::UserNotFound);
+
+    // The DID is free for a new registration
+    Client::new(&router).register(&mut passkey, DID).await;
+}
+
+#[tokio::test]
+async fn rejects_unknown_user_handles() {
+    let mut passkey = passkey();
+
+    // Registered with another server
+    Client::new(&router()).register(&mut passkey, DID).await;
+
+    let router = router();
+    let mut client = Client::new(&router);
+
+    let res: StartDiscoverableAuthenticationResponse =
+        client.post("/auth/discover/start", &BUTTON).await.json();
+    let assertion = passkey.get(&res.challenge, b"").unwrap();
+
+    client
+        .post(
+            "/auth/discover/finish",
+            &FinishDiscoverableAuthenticationRequest {
+                credential: assertion.credential,
+            },
+        )
+        .await
+        .error(StatusCode::NOT_FOUND, ErrorCode::UserNotFound);
+}
+
+#[tokio::test]
+async fn rejects_duplicate_dids() {
+    let router = router();
+    let mut passkey = passkey();
+
+    // An unfinished registration doesn't hold on to the DID, the first one
+    // to finish takes it
+    let mut pending = Client::new(&router);
+    let res: StartRegistrationResponse = pending
+        .post(
+            "/register/start",
+            &StartRegistrationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .json();
+
+    Client::new(&router).register(&mut passkey, DID).await;
+
+    let mut other = SoftPasskey::new(Config::default().rp.origin);
+    let registration = other.create(&res.challenge, b"").unwrap();
+    let keys = derive(&registration.prf, DID, Algorithm::P256);
+    let challenge = &res.challenge.public_key.challenge;
+
+    pending
+        .post(
+            "/register/finish",
+            &FinishRegistrationRequest {
+                credential: registration.credential,
+                derivation: Version::LATEST,
+                signing_key: key_proof(&keys.signing, DID, challenge),
+                rotation_key: key_proof(&keys.rotation, DID, challenge),
+            },
+        )
+        .await
+        .error(StatusCode::CONFLICT, ErrorCode::UserExists);
+
+    Client::new(&router)
+        .post(
+            "/register/start",
+            &StartRegistrationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .error(StatusCode::CONFLICT, ErrorCode::UserExists);
+}
+
+#[tokio::test]
+async fn rejects_unknown_users() {
+    let router = router();
+
+    Client::new(&router)
+        .post(
+            "/auth/start",
+            &StartAuthenticationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .error(StatusCode::NOT_FOUND, ErrorCode::UserNotFound);
+
+    Client::new(&router)
+        .get(&format!("/keys/{DID}"))
+        .await
+        .error(StatusCode::NOT_FOUND, ErrorCode::UserNotFound);
+}
+
+#[tokio::test]
+async fn rejects_wrong_session_state() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    client.register(&mut passkey, DID).await;
+    let req = client.start_login(&mut passkey, DID, Algorithm::P256).await;
+
+    // A login in progress doesn't allow finishing a registration
+    let res: StartRegistrationResponse = Client::new(&router)
+        .post(
+            "/register/start",
+            &StartRegistrationRequest {
+                did: "did:plc:other".to_owned(),
+            },
+        )
+        .await
+        .json();
+
+    let registration = passkey.create(&res.challenge, b"").unwrap();
+    let keys = derive(&registration.prf, DID, Algorithm::P256);
+    let challenge = &res.challenge.public_key.challenge;
+
+    client
+        .post(
+            "/register/finish",
+            &FinishRegistrationRequest {
+                credential: registration.credential,
+                derivation: Version::LATEST,
+                signing_key: key_proof(&keys.signing, DID, challenge),
+                rotation_key: key_proof(&keys.rotation, DID, challenge),
+            },
+        )
+        .await
+        .error(StatusCode::BAD_REQUEST, ErrorCode::NoCeremony);
+
+    // Another session never started the login
+    Client::new(&router)
+        .post("/auth/finish", &req)
+        .await
+        .error(StatusCode::BAD_REQUEST, ErrorCode::NoCeremony);
+
+    Client::new(&router)
+        .post("/credentials/add/start", &())
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated);
+}
+
+#[tokio::test]
+async fn rejects_replayed_credentials() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    client.register(&mut passkey, DID).await;
+
+    let req = client.start_login(&mut passkey, DID, Algorithm::P256).await;
+    let _: FinishAuthenticationResponse = client.post("/auth/finish", &req).await.json();
+
+    // The ceremony is over once finished
+    client
+        .post("/auth/finish", &req)
+        .await
+        .error(StatusCode::BAD_REQUEST, ErrorCode::NoCeremony);
+
+    // And its assertion doesn't answer a new challenge
+    let _: StartAuthenticationResponse = client
+        .post(
+            "/auth/start",
+            &StartAuthenticationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .json();
+
+    client
+        .post("/auth/finish", &req)
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::AuthenticationFailed);
+
+    // Nor a new usernameless one
+    let mut other = Client::new(&router);
+    let res: StartDiscoverableAuthenticationResponse =
+        other.post("/auth/discover/start", &BUTTON).await.json();
+    let req = FinishDiscoverableAuthenticationRequest {
+        credential: passkey.get(&res.challenge, b"").unwrap().credential,
+    };
+    let _: FinishDiscoverableAuthenticationResponse =
+        other.post("/auth/discover/finish", &req).await.json();
+
+    let _: StartDiscoverableAuthenticationResponse =
+        other.post("/auth/discover/start", &BUTTON).await.json();
+    other
+        .post("/auth/discover/finish", &req)
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::AuthenticationFailed);
+}
+
+#[tokio::test]
+async fn rejects_mismatched_keys() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    client.register(&mut passkey, DID).await;
+
+    let req = client
+        .start_login(&mut passkey, DID, Algorithm::Secp256k1)
+        .await;
+
+    client
+        .post("/auth/finish", &req)
+        .await
+        .error(StatusCode::CONFLICT, ErrorCode::KeyMismatch);
+
+    // A failed ceremony is over as well
+    client
+        .post("/auth/finish", &req)
+        .await
+        .error(StatusCode::BAD_REQUEST, ErrorCode::NoCeremony);
+
+    // A usernameless login checks the key on the server as well
+    let (_, req) = client.identify(&mut passkey, Algorithm::Secp256k1).await;
+
+    client
+        .post("/auth/discover/verify", &req)
+        .await
+        .error(StatusCode::CONFLICT, ErrorCode::KeyMismatch);
+
+    client
+        .post("/auth/discover/verify", &req)
+        .await
+        .error(StatusCode::BAD_REQUEST, ErrorCode::NoCeremony);
+
+    client
+        .get("/auth/me")
+        .await
+        .error(StatusCode::UNAUTHORIZED, ErrorCode::Unauthenticated);
+}
+
+#[tokio::test]
+async fn rejects_expired_ceremonies() {
+    let router = router_with(Session {
+        ceremony_expiry_secs: 0,
+        ..Session::default()
+    });
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    let res: StartRegistrationResponse = client
+        .post(
+            "/register/start",
+            &StartRegistrationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .json();
+
+    let registration = passkey.create(&res.challenge, b"").unwrap();
+    let keys = derive(&registration.prf, DID, Algorithm::P256);
+    let challenge = &res.challenge.public_key.challenge;
+
+    let req = FinishRegistrationRequest {
+        credential: registration.credential,
+        derivation: Version::LATEST,
+        signing_key: key_proof(&keys.signing, DID, challenge),
+        rotation_key: key_proof(&keys.rotation, DID, challenge),
+    };
+
+    client
+        .post("/register/finish", &req)
+        .await
+        .error(StatusCode::BAD_REQUEST, ErrorCode::CeremonyExpired);
+
+    client
+        .post("/register/finish", &req)
+        .await
+        .error(StatusCode::BAD_REQUEST, ErrorCode::NoCeremony);
+
+    // The expired registration didn't take the DID
+    let _: StartRegistrationResponse = client
+        .post(
+            "/register/start",
+            &StartRegistrationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .json();
+}
+
+#[test]
+fn rejects_ceremonies_outliving_sessions() {
+    let config = Config {
+        session: Session {
+            expiry_secs: 60,
+            ceremony_expiry_secs: 60,
+        },
+        ..Config::default()
+    };
+
+    assert!(config.validate().is_err());
+    assert!(App::new(config).into_router().is_err());
+}
+
+#[tokio::test]
+async fn rejects_invalid_proofs() {
+    let router = router();
+    let mut client = Client::new(&router);
+    let mut passkey = passkey();
+
+    let res: StartRegistrationResponse = client
+        .post(
+            "/register/start",
+            &StartRegistrationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .json();
+
+    let registration = passkey.create(&res.challenge, b"").unwrap();
+    let keys = derive(&registration.prf, DID, Algorithm::P256);
+    let challenge = &res.challenge.public_key.challenge;
+
+    client
+        .post(
+            "/register/finish",
+            &FinishRegistrationRequest {
+                credential: registration.credential,
+                derivation: Version::LATEST,
+                signing_key: key_proof(&keys.signing, DID, challenge),
+                rotation_key: key_proof(&keys.rotation, DID, b"another challenge"),
+            },
+        )
+        .await
+        .error(StatusCode::BAD_REQUEST, ErrorCode::InvalidProof);
+
+    // The failed registration didn't take the DID
+    let keys = client.register(&mut passkey, DID).await;
+
+    // A usernameless login needs a proof over its own challenge
+    let (_, mut req) = client.identify(&mut passkey, Algorithm::P256).await;
+    req.signing_key = key_proof(&keys.signing, DID, b"another challenge");
+
+    client
+        .post("/auth/discover/verify", &req)
+        .await
+        .error(StatusCode::BAD_REQUEST, ErrorCode::InvalidProof);
+}
+
+#[tokio::test]
+async fn rejects_malformed_requests() {
+    Client::new(&router())
+        .post("/auth/start", &serde_json::json!({ "user": DID }))
+        .await
+        .error(StatusCode::BAD_REQUEST, ErrorCode::BadRequest);
+}
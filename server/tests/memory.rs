@@ -0,0 +1,5 @@
+//! Runs the ceremony tests against the in-memory user store.
+
+mod ceremonies;
+
+const SQLITE: bool = false;
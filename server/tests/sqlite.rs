@@ -0,0 +1,212 @@
+//! Runs the ceremony tests against the SQLite user store, and checks that
+//! databases created by earlier schema versions are migrated.
+
+mod ceremonies;
+
+const SQLITE: bool = true;
+
+use std::path::Path;
+
+use api::*;
+use axum::Router;
+use keygen::{Algorithm, Keygen, Version, didkey};
+use rusqlite::{Connection, params};
+use server::{App, Config};
+
+use ceremonies::{Client, DID, TempDatabase, passkey};
+
+/// The schema before the first migration, at `user_version` 0.
+const V0_SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+
+    CREATE TABLE users (
+        id TEXT PRIMARY KEY NOT NULL,
+        did TEXT NOT NULL UNIQUE
+    );
+
+    CREATE TABLE passkeys (
+        credential_id BLOB PRIMARY KEY NOT NULL,
+        user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        passkey TEXT NOT NULL,
+        nickname TEXT,
+        created_at INTEGER NOT NULL,
+        last_used_at INTEGER
+    );
+";
+
+const SCHEMA_VERSION: usize = 4;
+
+fn router(database: &Path) -> Router {
+    let config = Config {
+        database: Some(database.to_owned()),
+        ..Config::default()
+    };
+
+    App::new(config).into_router().unwrap()
+}
+
+fn schema_version(database: &Path) -> usize {
+    Connection::open(database)
+        .unwrap()
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap()
+}
+
+async fn stored_derivations(client: &mut Client) -> Vec<CredentialDerivation> {
+    let res: StartAuthenticationResponse = client
+        .post(
+            "/auth/start",
+            &StartAuthenticationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .json();
+
+    res.derivations
+}
+
+#[tokio::test]
+async fn migrates_v0_databases() {
+    let mut passkey = passkey();
+
+    // Only the server can produce a stored passkey, so register one and copy
+    // it into a database with the original schema
+    let current = TempDatabase::new();
+    Client::new(&router(current.path()))
+        .register(&mut passkey, DID)
+        .await;
+
+    let (user_id, credential_id, stored, created_at): (String, Vec<u8>, String, i64) =
+        Connection::open(current.path())
+            .unwrap()
+            .query_row(
+                "SELECT user_id, credential_id, passkey, created_at FROM passkeys",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+
+    let legacy = TempDatabase::new();
+    let conn = Connection::open(legacy.path()).unwrap();
+    conn.execute_batch(V0_SCHEMA).unwrap();
+    conn.execute(
+        "INSERT INTO users (id, did) VALUES (?1, ?2)",
+        params![user_id, DID],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO passkeys (credential_id, user_id, passkey, created_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![credential_id, user_id, stored, created_at],
+    )
+    .unwrap();
+    drop(conn);
+
+    assert_eq!(schema_version(legacy.path()), 0);
+
+    let router = router(legacy.path());
+    let mut client = Client::new(&router);
+
+    assert_eq!(schema_version(legacy.path()), SCHEMA_VERSION);
+
+    // Credentials from before versioned derivation use v1, and their key type
+    // is unknown until a login binds the key
+    let derivations = stored_derivations(&mut client).await;
+    assert_eq!(derivations.len(), 1);
+    assert_eq!(derivations[0].credential_id, credential_id);
+    assert_eq!(derivations[0].derivation, Version::V1);
+    assert_eq!(derivations[0].algorithm, None);
+
+    let req = client
+        .start_login(&mut passkey, DID, Algorithm::Secp256k1)
+        .await;
+    let _: FinishAuthenticationResponse = client.post("/auth/finish", &req).await.json();
+
+    let derivations = stored_derivations(&mut Client::new(&router)).await;
+    assert_eq!(derivations[0].algorithm, Some(Algorithm::Secp256k1));
+
+    // The bound key is the one derived with v1
+    let res: StartAuthenticationResponse = Client::new(&router)
+        .post(
+            "/auth/start",
+            &StartAuthenticationRequest {
+                did: DID.to_owned(),
+            },
+        )
+        .await
+        .json();
+    let prf = passkey.get(&res.challenge, b"").unwrap().prf;
+    let keys = Keygen::new(Version::V1)
+        .generate_keys(&prf, DID.as_bytes(), Algorithm::Secp256k1)
+        .unwrap();
+    let signing_key = didkey::encode(Algorithm::Secp256k1, &keys.signing.public_key()).unwrap();
+    assert_eq!(req.signing_key.did_key, signing_key);
+}
+
+#[test]
+fn refuses_newer_databases() {
+    let database = TempDatabase::new();
+    let conn = Connection::open(database.path()).unwrap();
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+        .unwrap();
+    drop(conn);
+
+    let config = Config {
+        database: Some(database.path().to_owned()),
+        ..Config::default()
+    };
+
+    let err = App::new(config).into_router().err().unwrap();
+    assert!(format!("{err:?}").contains("newer than the supported"));
+    assert_eq!(schema_version(database.path()), SCHEMA_VERSION + 1);
+}
+
+#[tokio::test]
+async fn backfills_credential_algorithms() {
+    let mut passkey = passkey();
+
+    let database = TempDatabase::new();
+    let mut client = Client::new(&router(database.path()));
+    client.register(&mut passkey, DID).await;
+
+    // Roll the database back to before the algorithm was recorded
+    let conn = Connection::open(database.path()).unwrap();
+    conn.execute_batch(
+        "ALTER TABLE passkeys DROP COLUMN algorithm;
+         PRAGMA user_version = 3;",
+    )
+    .unwrap();
+    drop(conn);
+
+    // The algorithm is read off the bound signing key
+    let mut client = Client::new(&router(database.path()));
+    let derivations = stored_derivations(&mut client).await;
+    assert_eq!(derivations[0].derivation, Version::LATEST);
+    assert_eq!(derivations[0].algorithm, Some(Algorithm::P256));
+    assert_eq!(schema_version(database.path()), SCHEMA_VERSION);
+}
+
+#[tokio::test]
+async fn deletes_passkeys_with_users() {
+    let mut passkey = passkey();
+
+    let database = TempDatabase::new();
+    let router = router(database.path());
+    let mut client = Client::new(&router);
+    client.register(&mut passkey, DID).await;
+
+    let req = client.start_login(&mut passkey, DID, Algorithm::P256).await;
+    let _: FinishAuthenticationResponse = client.post("/auth/finish", &req).await.json();
+    let _: DeleteAccountResponse = client.post("/account/delete", &()).await.json();
+
+    let conn = Connection::open(database.path()).unwrap();
+    for table in ["users", "passkeys"] {
+        let count: u64 = conn
+            .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 0, "{table}");
+    }
+}